@@ -0,0 +1,192 @@
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use std::collections::BinaryHeap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+const MAGIC: &[u8; 4] = b"RVBQ";
+const VERSION: u32 = 1;
+const HEADER_LEN: u64 = 12;
+
+/// Sign-bit copy of every vector in an RVIX file (`reviews.index.bq`).
+///
+/// Each record is `id` + one bit per dimension (1 = component > 0) packed into
+/// u64 words, in the same order as the RVIX records, so a record's position
+/// here is its slot in `reviews.index`. The words are also kept in memory
+/// so `shortlist` never touches the file.
+#[derive(Debug)]
+pub struct BinaryCodes {
+    path: String,
+    dim: usize,
+    // id ของแต่ละ record ที่เขียนครบแล้ว (index = slot)
+    ids: Vec<u64>,
+    // words ของทุก record ต่อกัน (slot * words_per_record)
+    words: Vec<u64>,
+}
+
+impl BinaryCodes {
+    pub fn open_or_create(path: impl Into<String>, dim: usize) -> io::Result<Self> {
+        let mut codes = Self {
+            path: path.into(),
+            dim,
+            ids: Vec::new(),
+            words: Vec::new(),
+        };
+
+        let valid = Path::new(&codes.path).exists() && codes.read_header().is_ok();
+        if !valid {
+            codes.reset()?;
+        } else {
+            codes.load()?;
+        }
+
+        Ok(codes)
+    }
+
+    /// id ของ record ที่เขียนครบ เรียงตาม slot
+    pub fn ids(&self) -> &[u64] {
+        &self.ids
+    }
+
+    /// ล้างไฟล์ให้เหลือแค่ header
+    pub fn reset(&mut self) -> io::Result<()> {
+        let mut f = File::create(&self.path)?;
+        f.write_all(MAGIC)?;
+        f.write_u32::<LittleEndian>(VERSION)?;
+        f.write_u32::<LittleEndian>(self.dim as u32)?;
+        f.flush()?;
+
+        self.ids.clear();
+        self.words.clear();
+        Ok(())
+    }
+
+    /// ตัดไฟล์ให้เหลือ `count` record แรก (รวมถึง record ที่เขียนไม่ครบท้ายไฟล์)
+    pub fn truncate(&mut self, count: usize) -> io::Result<()> {
+        let f = OpenOptions::new().write(true).open(&self.path)?;
+        f.set_len(HEADER_LEN + count as u64 * self.record_bytes())?;
+        f.sync_data()?;
+
+        self.ids.truncate(count);
+        self.words.truncate(count * self.words_per_record());
+        Ok(())
+    }
+
+    pub fn append(&mut self, records: &[(u64, &[f32])]) -> io::Result<()> {
+        let f = OpenOptions::new().append(true).open(&self.path)?;
+        let mut w = BufWriter::new(f);
+        for &(id, vec) in records {
+            let words = Self::encode(vec);
+            w.write_u64::<LittleEndian>(id)?;
+            for &word in &words {
+                w.write_u64::<LittleEndian>(word)?;
+            }
+            self.ids.push(id);
+            self.words.extend(words);
+        }
+        w.flush()
+    }
+
+    /// เขียนทับ code ของ record ที่ `slot` (id เดิม)
+    pub fn replace(&mut self, slot: usize, vec: &[f32]) -> io::Result<()> {
+        let words = Self::encode(vec);
+
+        let mut f = OpenOptions::new().write(true).open(&self.path)?;
+        // ข้าม id ของ record
+        f.seek(SeekFrom::Start(
+            HEADER_LEN + slot as u64 * self.record_bytes() + 8,
+        ))?;
+        let mut buf = Vec::with_capacity(words.len() * 8);
+        for &word in &words {
+            buf.write_u64::<LittleEndian>(word)?;
+        }
+        f.write_all(&buf)?;
+        f.sync_data()?;
+
+        let n = self.words_per_record();
+        self.words[slot * n..(slot + 1) * n].copy_from_slice(&words);
+        Ok(())
+    }
+
+    /// code ของ record ที่ `slot`
+    pub fn code_at(&self, slot: usize) -> &[u64] {
+        let n = self.words_per_record();
+        &self.words[slot * n..(slot + 1) * n]
+    }
+
+    /// scan ด้วย Hamming distance แล้วคืน slot ของ `n` record ที่ใกล้ที่สุด
+    /// (เฉพาะ slot ที่ `keep` คืน true)
+    pub fn shortlist(&self, query: &[f32], n: usize, keep: impl Fn(usize) -> bool) -> Vec<usize> {
+        let q = Self::encode(query);
+
+        // max-heap (hamming, slot) เก็บแค่ n ตัวที่ดีที่สุด
+        let mut best: BinaryHeap<(u32, usize)> = BinaryHeap::with_capacity(n + 1);
+        for (slot, words) in self.words.chunks_exact(q.len()).enumerate() {
+            if !keep(slot) {
+                continue;
+            }
+            let dist: u32 = q.iter().zip(words).map(|(a, b)| (a ^ b).count_ones()).sum();
+            if best.len() < n {
+                best.push((dist, slot));
+            } else if let Some(&(worst, _)) = best.peek() {
+                if dist < worst {
+                    best.pop();
+                    best.push((dist, slot));
+                }
+            }
+        }
+
+        best.into_sorted_vec().into_iter().map(|(_, s)| s).collect()
+    }
+
+    fn load(&mut self) -> io::Result<()> {
+        let f = File::open(&self.path)?;
+        let mut r = BufReader::new(f);
+        r.seek(SeekFrom::Start(HEADER_LEN))?;
+
+        // โหลดเฉพาะ record ที่เขียนครบ (ส่วนที่เขียนไม่ครบท้ายไฟล์ถูกตัดตอน truncate)
+        let body = fs::metadata(&self.path)?.len().saturating_sub(HEADER_LEN);
+        let count = (body / self.record_bytes()) as usize;
+        let mut words = vec![0u64; self.words_per_record()];
+        for _ in 0..count {
+            self.ids.push(r.read_u64::<LittleEndian>()?);
+            r.read_u64_into::<LittleEndian>(&mut words)?;
+            self.words.extend_from_slice(&words);
+        }
+        Ok(())
+    }
+
+    fn read_header(&self) -> io::Result<()> {
+        let mut f = File::open(&self.path)?;
+        let mut magic = [0u8; 4];
+        f.read_exact(&mut magic)?;
+        let version = f.read_u32::<LittleEndian>()?;
+        let dim = f.read_u32::<LittleEndian>()? as usize;
+        if &magic != MAGIC || version != VERSION || dim != self.dim {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "bad binary code header",
+            ));
+        }
+        Ok(())
+    }
+
+    fn words_per_record(&self) -> usize {
+        self.dim.div_ceil(64)
+    }
+
+    fn record_bytes(&self) -> u64 {
+        8 + self.words_per_record() as u64 * 8
+    }
+
+    /// sign-bit ของ `vec` (1 = ค่า > 0) อัดเป็น u64 words
+    pub fn encode(vec: &[f32]) -> Vec<u64> {
+        let mut words = vec![0u64; vec.len().div_ceil(64)];
+        for (i, &v) in vec.iter().enumerate() {
+            if v > 0.0 {
+                words[i / 64] |= 1 << (i % 64);
+            }
+        }
+        words
+    }
+}
@@ -0,0 +1,477 @@
+use axum::{
+    extract::{FromRequestParts, RawPathParams},
+    http::request::Parts,
+    response::{IntoResponse, Response},
+};
+use fastembed::{EmbeddingModel, InitOptions, TextEmbedding};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tokio::sync::{Mutex, RwLock};
+
+use crate::distance::Metric;
+use crate::doc_store::{open_document_store, DocumentStore};
+use crate::flat_index::Precision;
+use crate::model::{
+    CreateCollectionRequest, DocumentSettings, EmbeddingSettings, IndexDef, IndexKind,
+    IndexSettings,
+};
+use crate::presenter::res_error_msg;
+use crate::reconcile::reconcile;
+use crate::schema::Schema;
+use crate::template::EmbeddingTemplate;
+use crate::vector_index::{open_index, VectorIndex};
+use crate::AppState;
+
+/// collection เดิมของ service: ไฟล์อยู่ที่ src/data/reviews.* และ route ก่อนมี collection ใช้ตัวนี้
+pub const DEFAULT_COLLECTION: &str = "reviews";
+const DATA_DIR: &str = "src/data";
+// collection อื่นอยู่ directory ละ collection
+const COLLECTIONS_DIR: &str = "src/data/collections";
+const MAX_NAME: usize = 64;
+
+/// Files of one collection, all named after it: `<dir>/<name>.index`,
+/// `.jsonl`, `.redb`, `.schema.json`, `.embedding.json` and `.index.json`.
+#[derive(Debug, Clone)]
+pub struct CollectionPaths {
+    pub dir: String,
+    pub index: String,
+    pub jsonl: String,
+    pub redb: String,
+    pub schema: String,
+    // model + template (reviews ใช้ค่าใน config.yml แทน)
+    pub embedding: String,
+    // kind + precision + metric ของ index (reviews ใช้ค่าใน config.yml แทน)
+    pub index_def: String,
+}
+
+impl CollectionPaths {
+    pub fn of(name: &str) -> Self {
+        if name == DEFAULT_COLLECTION {
+            Self::in_dir(DATA_DIR, name)
+        } else {
+            Self::in_dir(&format!("{}/{}", COLLECTIONS_DIR, name), name)
+        }
+    }
+
+    fn in_dir(dir: &str, name: &str) -> Self {
+        Self {
+            dir: dir.to_string(),
+            index: format!("{}/{}.index", dir, name),
+            jsonl: format!("{}/{}.jsonl", dir, name),
+            redb: format!("{}/{}.redb", dir, name),
+            schema: format!("{}/{}.schema.json", dir, name),
+            embedding: format!("{}/{}.embedding.json", dir, name),
+            index_def: format!("{}/{}.index.json", dir, name),
+        }
+    }
+}
+
+/// What a collection holds and how its documents are embedded.
+pub struct CollectionDef {
+    pub schema: Schema,
+    pub embedding: EmbeddingSettings,
+    pub index: IndexDef,
+}
+
+impl CollectionDef {
+    /// อ่าน definition จากไฟล์ของ collection (reviews ใช้ embedding + index จาก config.yml)
+    /// collection ที่สร้างก่อนมี `<name>.index.json` ใช้ index จาก config.yml
+    pub fn load(
+        name: &str,
+        reviews: &EmbeddingSettings,
+        index: &IndexSettings,
+    ) -> Result<Self, String> {
+        let paths = CollectionPaths::of(name);
+        let schema = Schema::load(&paths.schema)?;
+        if name == DEFAULT_COLLECTION {
+            return Ok(Self {
+                schema,
+                embedding: reviews.clone(),
+                index: IndexDef::from_settings(index),
+            });
+        }
+
+        let content = fs::read_to_string(&paths.embedding)
+            .map_err(|e| format!("{}: {}", paths.embedding, e))?;
+        let embedding =
+            serde_json::from_str(&content).map_err(|e| format!("{}: {}", paths.embedding, e))?;
+        let index = match fs::read_to_string(&paths.index_def) {
+            Ok(content) => {
+                serde_json::from_str(&content).map_err(|e| format!("{}: {}", paths.index_def, e))?
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => IndexDef::from_settings(index),
+            Err(e) => return Err(format!("{}: {}", paths.index_def, e)),
+        };
+        Ok(Self {
+            schema,
+            embedding,
+            index,
+        })
+    }
+}
+
+/// model ของ fastembed จากชื่อ -> (model, dim, model code ที่บันทึกใน header ของ index)
+pub fn model_info(name: &str) -> Result<(EmbeddingModel, usize, String), String> {
+    let model = EmbeddingModel::from_str(name)?;
+    let info = TextEmbedding::get_model_info(&model).map_err(|e| e.to_string())?;
+    let (dim, code) = (info.dim, info.model_code.clone());
+    Ok((model, dim, code))
+}
+
+/// Loaded embedding models, shared by the collections that use the same one.
+#[derive(Default)]
+pub struct Embedders {
+    loaded: std::sync::Mutex<HashMap<EmbeddingModel, Arc<Mutex<TextEmbedding>>>>,
+}
+
+impl Embedders {
+    /// โหลด model ครั้งแรกที่มี collection ใช้ (อาจต้อง download) -> เรียกจาก blocking thread
+    pub fn get(&self, model: &EmbeddingModel) -> Result<Arc<Mutex<TextEmbedding>>, String> {
+        let mut loaded = self.loaded.lock().unwrap();
+        if let Some(embedder) = loaded.get(model) {
+            return Ok(embedder.clone());
+        }
+
+        let mut opts = InitOptions::default();
+        opts.model_name = model.clone();
+        let embedder = TextEmbedding::try_new(opts)
+            .map_err(|e| format!("failed to init embedding model {}: {}", model, e))?;
+        let embedder = Arc::new(Mutex::new(embedder));
+        loaded.insert(model.clone(), embedder.clone());
+        Ok(embedder)
+    }
+}
+
+/// One named collection: its own schema, embedding model and template,
+/// vector index and document store.
+pub struct Collection {
+    pub name: String,
+    pub paths: CollectionPaths,
+    pub schema: Schema,
+    pub embedding: EmbeddingSettings,
+    pub index_def: IndexDef,
+    pub template: EmbeddingTemplate,
+    pub embedder: Arc<Mutex<TextEmbedding>>,
+    pub index: Arc<RwLock<Box<dyn VectorIndex>>>,
+    pub documents: Arc<RwLock<Box<dyn DocumentStore>>>,
+    // true = ถูก drop แล้ว (request ที่ถือ Arc ไว้ก่อน drop ต้องไม่เขียนอะไรอีก)
+    dropped: AtomicBool,
+}
+
+/// Summary of a collection for `GET /collections`.
+#[derive(Debug, Serialize)]
+pub struct CollectionInfo {
+    pub name: String,
+    pub model: String,
+    pub template: String,
+    pub dim: usize,
+    pub metric: Metric,
+    pub kind: IndexKind,
+    pub precision: Precision,
+    pub documents: usize,
+}
+
+impl Collection {
+    /// เปิด index + document store (ยังไม่มี = สร้างใหม่) แล้ว reconcile ให้มี id ชุดเดียวกัน
+    /// `index_settings` = ค่าของ config.yml, kind/precision/metric มาจาก `def.index`
+    pub fn open(
+        name: &str,
+        def: CollectionDef,
+        index_settings: &IndexSettings,
+        document_settings: &DocumentSettings,
+        embedders: &Embedders,
+    ) -> Result<Self, String> {
+        let paths = CollectionPaths::of(name);
+        let template = EmbeddingTemplate::parse(&def.embedding.template, &def.schema)
+            .map_err(|e| format!("collection {}: {}", name, e))?;
+        let (model, dim, model_code) =
+            model_info(&def.embedding.model).map_err(|e| format!("collection {}: {}", name, e))?;
+
+        let mut index = open_index(
+            &paths.index,
+            dim,
+            &model_code,
+            template.version(),
+            &def.index.settings(index_settings),
+        )
+        .map_err(|e| format!("failed to open vector index {}: {}", paths.index, e))?;
+        let mut documents =
+            open_document_store(&paths.jsonl, &paths.redb, document_settings, &def.schema)
+                .map_err(|e| format!("failed to open documents of {}: {}", name, e))?;
+
+        // งาน create/delete ที่ค้างตอน crash -> ให้ index กับ metadata มี id ตรงกัน
+        let report = reconcile(index.as_mut(), documents.as_mut())
+            .map_err(|e| format!("failed to reconcile documents of {}: {}", name, e))?;
+        if !report.is_clean() {
+            println!(
+                "reconciled index and documents of {}: {}",
+                name,
+                serde_json::to_string(&report).unwrap()
+            );
+        }
+        if !report.documents_without_vector.is_empty() {
+            eprintln!(
+                "{} documents of {} have no vector in {}; \
+                 run `backend reindex {} --rebuild` to make them searchable again",
+                report.documents_without_vector.len(),
+                name,
+                paths.index,
+                name
+            );
+        }
+
+        let embedder = embedders.get(&model)?;
+
+        Ok(Self {
+            name: name.to_string(),
+            paths,
+            schema: def.schema,
+            embedding: def.embedding,
+            index_def: def.index,
+            template,
+            embedder,
+            index: Arc::new(RwLock::new(index)),
+            documents: Arc::new(RwLock::new(documents)),
+            dropped: AtomicBool::new(false),
+        })
+    }
+
+    /// เช็คหลังได้ lock ของ index ก่อนเขียน: drop ตั้งค่านี้ตอนถือ lock ของ index อยู่
+    pub fn is_dropped(&self) -> bool {
+        self.dropped.load(Ordering::Acquire)
+    }
+
+    /// vector ใน index ถูก embed ด้วย template อื่น -> ต้อง `backend reindex <name>` ก่อนใช้
+    pub async fn needs_reindex(&self) -> bool {
+        self.index.read().await.template() != self.template.version()
+    }
+
+    pub async fn info(&self) -> CollectionInfo {
+        let (dim, metric) = {
+            let index = self.index.read().await;
+            (index.dim(), index.metric())
+        };
+        CollectionInfo {
+            name: self.name.clone(),
+            model: self.embedding.model.clone(),
+            template: self.template.text().to_string(),
+            dim,
+            metric,
+            kind: self.index_def.kind,
+            precision: self.index_def.precision,
+            documents: self.documents.read().await.len(),
+        }
+    }
+}
+
+/// All open collections, plus the settings new ones are opened with.
+pub struct Collections {
+    index: IndexSettings,
+    documents: DocumentSettings,
+    embedders: Embedders,
+    open: RwLock<HashMap<String, Arc<Collection>>>,
+    // create/drop ทีละ request
+    changing: std::sync::Mutex<()>,
+}
+
+impl Collections {
+    pub fn new(index: IndexSettings, documents: DocumentSettings) -> Self {
+        Self {
+            index,
+            documents,
+            embedders: Embedders::default(),
+            open: RwLock::new(HashMap::new()),
+            changing: std::sync::Mutex::new(()),
+        }
+    }
+
+    /// ชื่อ collection ที่มีบน disk: reviews + ทุก directory ใน src/data/collections
+    fn names() -> io::Result<Vec<String>> {
+        let mut names = vec![DEFAULT_COLLECTION.to_string()];
+        let entries = match fs::read_dir(COLLECTIONS_DIR) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(names),
+            Err(e) => return Err(e),
+        };
+        for entry in entries {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().to_string();
+            if !entry.file_type()?.is_dir() {
+                continue;
+            }
+            // `.<name>.tmp` = create ที่ไม่จบ -> ทิ้ง
+            if name.starts_with('.') {
+                let _ = fs::remove_dir_all(entry.path());
+                continue;
+            }
+            names.push(name);
+        }
+        names[1..].sort();
+        Ok(names)
+    }
+
+    /// เปิดทุก collection ตอน start (`reviews` = embedding ของ reviews จาก config.yml)
+    ///
+    /// reviews เปิดไม่ได้ = error, collection อื่นที่เปิดไม่ได้ถูกข้าม (log ไว้) ให้ start ต่อได้
+    pub fn open_all(&mut self, reviews: &EmbeddingSettings) -> Result<(), String> {
+        let names = Self::names().map_err(|e| format!("{}: {}", COLLECTIONS_DIR, e))?;
+        for name in names {
+            let opened = CollectionDef::load(&name, reviews, &self.index).and_then(|def| {
+                Collection::open(&name, def, &self.index, &self.documents, &self.embedders)
+            });
+            match opened {
+                Ok(collection) => {
+                    self.open.get_mut().insert(name, Arc::new(collection));
+                }
+                Err(e) if name == DEFAULT_COLLECTION => return Err(e),
+                Err(e) => eprintln!("skipping collection {}: {}", name, e),
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn get(&self, name: &str) -> Option<Arc<Collection>> {
+        self.open.read().await.get(name).cloned()
+    }
+
+    /// ทุก collection เรียงตามชื่อ
+    pub async fn list(&self) -> Vec<Arc<Collection>> {
+        let mut collections: Vec<Arc<Collection>> =
+            self.open.read().await.values().cloned().collect();
+        collections.sort_by(|a, b| a.name.cmp(&b.name));
+        collections
+    }
+
+    /// เขียน definition แล้วเปิด collection ใหม่ (index + document store ว่าง)
+    /// เรียกจาก blocking thread: อาจต้องโหลด embedding model
+    pub fn create(&self, req: CreateCollectionRequest) -> Result<Arc<Collection>, String> {
+        check_name(&req.name)?;
+        let schema = Schema::from_value(req.schema.clone(), "schema")?;
+        EmbeddingTemplate::parse(&req.embedding.template, &schema)?;
+        model_info(&req.embedding.model)?;
+        let index = req.index.resolve(&self.index);
+        if index.precision == Precision::Int8 {
+            return Err("index precision int8 needs vectors to calibrate from; \
+                 create the collection with f32 or f16 and switch it in its index.json later"
+                .to_string());
+        }
+
+        let _changing = self.changing.lock().unwrap();
+        let paths = CollectionPaths::of(&req.name);
+        if self.open.blocking_read().contains_key(&req.name) || Path::new(&paths.dir).exists() {
+            return Err(format!("collection {} already exists", req.name));
+        }
+
+        // เขียนลง `.<name>.tmp` แล้ว rename -> ไม่มี collection ที่ไฟล์ไม่ครบค้างอยู่
+        let tmp =
+            CollectionPaths::in_dir(&format!("{}/.{}.tmp", COLLECTIONS_DIR, req.name), &req.name);
+        let written = (|| -> io::Result<()> {
+            let _ = fs::remove_dir_all(&tmp.dir);
+            fs::create_dir_all(&tmp.dir)?;
+            fs::write(&tmp.schema, serde_json::to_string_pretty(&req.schema)?)?;
+            fs::write(
+                &tmp.embedding,
+                serde_json::to_string_pretty(&req.embedding)?,
+            )?;
+            fs::write(&tmp.index_def, serde_json::to_string_pretty(&index)?)?;
+            fs::rename(&tmp.dir, &paths.dir)
+        })();
+        if let Err(e) = written {
+            let _ = fs::remove_dir_all(&tmp.dir);
+            return Err(format!("failed to write collection {}: {}", req.name, e));
+        }
+
+        let def = CollectionDef {
+            schema,
+            embedding: req.embedding,
+            index,
+        };
+        let collection = match Collection::open(
+            &req.name,
+            def,
+            &self.index,
+            &self.documents,
+            &self.embedders,
+        ) {
+            Ok(collection) => Arc::new(collection),
+            Err(e) => {
+                let _ = fs::remove_dir_all(&paths.dir);
+                return Err(e);
+            }
+        };
+        self.open
+            .blocking_write()
+            .insert(req.name.clone(), collection.clone());
+        Ok(collection)
+    }
+
+    /// ลบ collection พร้อมไฟล์ทั้งหมด (reviews ลบไม่ได้ route เดิมใช้อยู่)
+    /// เรียกจาก blocking thread: รอ request ที่ถือ lock ของ collection อยู่ให้เสร็จก่อน
+    pub fn remove(&self, name: &str) -> Result<(), String> {
+        if name == DEFAULT_COLLECTION {
+            return Err(format!("collection {} cannot be dropped", name));
+        }
+
+        let _changing = self.changing.lock().unwrap();
+        let collection = self
+            .open
+            .blocking_write()
+            .remove(name)
+            .ok_or_else(|| format!("collection {} not found", name))?;
+
+        let _index = collection.index.blocking_write();
+        let _documents = collection.documents.blocking_write();
+        collection.dropped.store(true, Ordering::Release);
+        fs::remove_dir_all(&collection.paths.dir)
+            .map_err(|e| format!("failed to remove {}: {}", collection.paths.dir, e))
+    }
+}
+
+fn check_name(name: &str) -> Result<(), String> {
+    let valid = !name.is_empty()
+        && name.len() <= MAX_NAME
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'_' || b == b'-');
+    if valid {
+        Ok(())
+    } else {
+        Err(format!(
+            "collection name must be 1-{} characters of a-z, 0-9, _ and -",
+            MAX_NAME
+        ))
+    }
+}
+
+/// The collection a request works on: `{name}` of `/collections/{name}/...`,
+/// or reviews for the routes that predate collections.
+pub struct CollectionParam(pub Arc<Collection>);
+
+impl FromRequestParts<Arc<AppState>> for CollectionParam {
+    type Rejection = Response;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<AppState>,
+    ) -> Result<Self, Self::Rejection> {
+        let params = RawPathParams::from_request_parts(parts, state)
+            .await
+            .map_err(IntoResponse::into_response)?;
+        let name = params
+            .iter()
+            .find(|(key, _)| *key == "name")
+            .map_or(DEFAULT_COLLECTION, |(_, value)| value)
+            .to_string();
+
+        match state.collections.get(&name).await {
+            Some(collection) => Ok(Self(collection)),
+            None => Err(res_error_msg(format!("collection {} not found", name))),
+        }
+    }
+}
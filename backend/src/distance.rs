@@ -0,0 +1,247 @@
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+/// Distance function an index is built for; recorded in the RVIX header.
+///
+/// Every index sorts by `distance` ascending, so metrics where bigger is
+/// better (inner product) are stored negated; `score` turns a distance back
+/// into a "higher is better" number for API responses.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Metric {
+    /// 1 - cosine similarity (vector ถูก normalize ก่อนเก็บ)
+    #[default]
+    Cosine,
+    /// -(a · b) สำหรับ model ที่ train มาให้ใช้ inner product
+    Dot,
+    /// |a - b|^2
+    L2,
+    /// sum |a - b| (Manhattan)
+    L1,
+}
+
+impl Metric {
+    pub fn code(self) -> u32 {
+        match self {
+            Metric::Cosine => 0,
+            Metric::Dot => 1,
+            Metric::L2 => 2,
+            Metric::L1 => 3,
+        }
+    }
+
+    pub fn from_code(code: u32) -> Option<Self> {
+        match code {
+            0 => Some(Metric::Cosine),
+            1 => Some(Metric::Dot),
+            2 => Some(Metric::L2),
+            3 => Some(Metric::L1),
+            _ => None,
+        }
+    }
+
+    /// metric นี้เก็บ vector แบบ normalize แล้วหรือไม่
+    pub fn normalizes(self) -> bool {
+        matches!(self, Metric::Cosine)
+    }
+
+    /// ระยะระหว่าง 2 vector (น้อย = ใกล้)
+    pub fn distance(self, a: &[f32], b: &[f32]) -> f32 {
+        match self {
+            Metric::Cosine => cosine_distance(a, b),
+            Metric::Dot => -dot(a, b),
+            Metric::L2 => squared_l2(a, b),
+            Metric::L1 => l1_distance(a, b),
+        }
+    }
+
+    /// แปลง distance เป็น score (มาก = ใกล้): cosine similarity, inner product,
+    /// หรือ 1 / (1 + distance) สำหรับ L2/L1
+    pub fn score(self, distance: f32) -> f32 {
+        match self {
+            Metric::Cosine => 1.0 - distance,
+            Metric::Dot => -distance,
+            Metric::L2 | Metric::L1 => 1.0 / (1.0 + distance),
+        }
+    }
+
+    /// กลับด้านของ `score`: distance มากสุดที่ยังได้ score >= `score`
+    pub fn distance_for_score(self, score: f32) -> f32 {
+        match self {
+            Metric::Cosine => 1.0 - score,
+            Metric::Dot => -score,
+            // score ของ L2/L1 อยู่ใน (0, 1] -> <= 0 ผ่านทุก record
+            Metric::L2 | Metric::L1 if score <= 0.0 => f32::INFINITY,
+            Metric::L2 | Metric::L1 => 1.0 / score - 1.0,
+        }
+    }
+}
+
+/// dot product; ใช้ AVX2/FMA ถ้า CPU รองรับ ไม่งั้นใช้ 8 lane ที่ compiler vectorize ให้
+pub fn dot(a: &[f32], b: &[f32]) -> f32 {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if is_x86_feature_detected!("avx2") && is_x86_feature_detected!("fma") {
+            // SAFETY: เช็คแล้วว่า CPU รองรับ avx2 + fma
+            return unsafe { dot_avx2(a, b) };
+        }
+    }
+    dot_lanes(a, b)
+}
+
+fn dot_lanes(a: &[f32], b: &[f32]) -> f32 {
+    let n = a.len().min(b.len());
+    let (a, b) = (&a[..n], &b[..n]);
+
+    let mut acc = [0f32; 8];
+    let mut ca = a.chunks_exact(8);
+    let mut cb = b.chunks_exact(8);
+    for (x, y) in (&mut ca).zip(&mut cb) {
+        for i in 0..8 {
+            acc[i] += x[i] * y[i];
+        }
+    }
+    let tail: f32 = ca
+        .remainder()
+        .iter()
+        .zip(cb.remainder())
+        .map(|(x, y)| x * y)
+        .sum();
+
+    acc.iter().sum::<f32>() + tail
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2,fma")]
+unsafe fn dot_avx2(a: &[f32], b: &[f32]) -> f32 {
+    use std::arch::x86_64::*;
+
+    let n = a.len().min(b.len());
+    let chunks = n / 8;
+
+    let mut acc = _mm256_setzero_ps();
+    for i in 0..chunks {
+        let x = _mm256_loadu_ps(a.as_ptr().add(i * 8));
+        let y = _mm256_loadu_ps(b.as_ptr().add(i * 8));
+        acc = _mm256_fmadd_ps(x, y, acc);
+    }
+
+    let mut lanes = [0f32; 8];
+    _mm256_storeu_ps(lanes.as_mut_ptr(), acc);
+    let mut sum: f32 = lanes.iter().sum();
+    for i in chunks * 8..n {
+        sum += a[i] * b[i];
+    }
+    sum
+}
+
+pub fn norm(a: &[f32]) -> f32 {
+    dot(a, a).sqrt()
+}
+
+pub fn cosine_distance(a: &[f32], b: &[f32]) -> f32 {
+    let na = norm(a);
+    let nb = norm(b);
+    if na == 0.0 || nb == 0.0 {
+        1.0
+    } else {
+        let sim = dot(a, b) / (na * nb);
+        1.0 - sim
+    }
+}
+
+pub fn squared_l2(a: &[f32], b: &[f32]) -> f32 {
+    a.iter()
+        .zip(b.iter())
+        .map(|(x, y)| {
+            let d = x - y;
+            d * d
+        })
+        .sum()
+}
+
+pub fn l1_distance(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b.iter()).map(|(x, y)| (x - y).abs()).sum()
+}
+
+/// คืน vector ที่ normalize แล้ว (vector ศูนย์คืนค่าเดิม)
+pub fn normalized(a: &[f32]) -> Vec<f32> {
+    let n = norm(a);
+    if n == 0.0 {
+        a.to_vec()
+    } else {
+        a.iter().map(|x| x / n).collect()
+    }
+}
+
+/// เรียง (id, distance) จากใกล้ไปไกล (distance เท่ากัน -> id น้อยก่อน เหมือน `TopK`)
+pub fn sort_hits(hits: &mut [(u64, f32)]) {
+    hits.sort_unstable_by(|a, b| a.1.total_cmp(&b.1).then_with(|| a.0.cmp(&b.0)));
+}
+
+#[derive(Clone, Copy, PartialEq)]
+struct Hit {
+    dist: f32,
+    id: u64,
+}
+
+impl Eq for Hit {}
+
+impl Ord for Hit {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.dist
+            .total_cmp(&other.dist)
+            .then_with(|| self.id.cmp(&other.id))
+    }
+}
+
+impl PartialOrd for Hit {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Bounded max-heap that keeps the `k` smallest distances seen so far.
+pub struct TopK {
+    k: usize,
+    heap: BinaryHeap<Hit>,
+}
+
+impl TopK {
+    pub fn new(k: usize) -> Self {
+        Self {
+            k,
+            heap: BinaryHeap::with_capacity(k + 1),
+        }
+    }
+
+    pub fn push(&mut self, id: u64, dist: f32) {
+        let hit = Hit { dist, id };
+        if self.heap.len() < self.k {
+            self.heap.push(hit);
+        } else if let Some(worst) = self.heap.peek() {
+            // distance เท่ากัน -> id น้อยกว่าชนะ (ผลเหมือนกันไม่ว่าจะ push ลำดับไหน)
+            if hit < *worst {
+                self.heap.pop();
+                self.heap.push(hit);
+            }
+        }
+    }
+
+    /// รวมผลของอีก heap (เช่นจาก thread อื่น) เข้ามา
+    pub fn merge(&mut self, other: TopK) {
+        for hit in other.heap {
+            self.push(hit.id, hit.dist);
+        }
+    }
+
+    /// (id, distance) เรียงจากใกล้ไปไกล
+    pub fn into_sorted_vec(self) -> Vec<(u64, f32)> {
+        self.heap
+            .into_sorted_vec()
+            .into_iter()
+            .map(|h| (h.id, h.dist))
+            .collect()
+    }
+}
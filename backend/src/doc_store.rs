@@ -0,0 +1,98 @@
+use serde_json::Value;
+use std::io;
+use std::path::Path;
+
+use crate::jsonl_store::JsonlStore;
+use crate::model::{DocumentSettings, StoreKind};
+use crate::redb_store::RedbStore;
+use crate::schema::Schema;
+use crate::vector_index::CompactReport;
+
+pub fn parse_u64(v: &Value) -> Option<u64> {
+    match v {
+        Value::Number(n) => n.as_u64(),
+        Value::String(s) => s.parse::<u64>().ok(),
+        _ => None,
+    }
+}
+
+/// Common interface of the metadata stores, so handlers don't care where the
+/// documents live. Every document is a JSON object that carries its own `id`.
+pub trait DocumentStore: Send + Sync {
+    /// จำนวน document ที่ยังไม่ถูกลบ
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn get(&self, id: u64) -> io::Result<Option<Value>> {
+        Ok(self.get_many(&[id])?.pop().flatten())
+    }
+
+    /// document ของแต่ละ id ตามลำดับที่ขอ (None = ไม่มี/ถูกลบแล้ว)
+    fn get_many(&self, ids: &[u64]) -> io::Result<Vec<Option<Value>>>;
+
+    /// เพิ่ม document ใหม่ หรือแทนของเดิมที่มี id เดียวกัน
+    fn put(&mut self, id: u64, doc: &Value) -> io::Result<()>;
+
+    /// ลบ document คืน false ถ้าไม่มี id นี้หรือถูกลบไปแล้ว
+    fn delete(&mut self, id: u64) -> io::Result<bool>;
+
+    /// อ่านทุก document ที่ยังไม่ถูกลบ แล้วเรียก `f(id, doc)`
+    fn scan(&self, f: &mut dyn FnMut(u64, &Value)) -> io::Result<()>;
+
+    /// field ที่ store เก็บค่าไว้ใน memory (ดู `scan_fields`)
+    fn filter_fields(&self) -> &[String] {
+        &[]
+    }
+
+    /// เหมือน `scan` แต่ `doc` มีแค่ field ใน `filter_fields` และไม่อ่าน document จาก disk
+    fn scan_fields(&self, _f: &mut dyn FnMut(u64, &Value)) {}
+
+    /// id ของ document ที่ `field` == `value` จาก secondary index
+    /// (None = store ไม่ได้ index field/ค่านี้ไว้ ต้อง scan เอง)
+    fn lookup(&self, _field: &str, _value: &Value) -> io::Result<Option<Vec<u64>>> {
+        Ok(None)
+    }
+
+    /// คืนพื้นที่ของ document ที่ถูกลบ/แก้ไปแล้ว
+    fn compact(&mut self) -> io::Result<CompactReport> {
+        Ok(CompactReport::default())
+    }
+}
+
+/// เปิด store ตาม config; redb ที่ยังไม่มีไฟล์จะ import document จาก JSONL เดิมให้ครั้งแรก
+/// redb ทำ secondary index ของ `indexed` ของ schema, JSONL เก็บค่าของ `filter_fields` ไว้ใน memory
+pub fn open_document_store(
+    json_path: &str,
+    db_path: &str,
+    settings: &DocumentSettings,
+    schema: &Schema,
+) -> io::Result<Box<dyn DocumentStore>> {
+    match settings.store {
+        StoreKind::Jsonl => Ok(Box::new(JsonlStore::open(
+            json_path,
+            schema.filter_fields(),
+        )?)),
+        StoreKind::Redb => {
+            let indexed = schema.indexed.as_deref();
+            if !Path::new(db_path).exists() && Path::new(json_path).exists() {
+                import_jsonl(json_path, db_path, indexed)?;
+            }
+            Ok(Box::new(RedbStore::open_or_create(db_path, indexed)?))
+        }
+    }
+}
+
+// import ลง tmp แล้ว rename (import ไม่จบ = ไม่มีไฟล์ db ครึ่งๆ ค้างไว้)
+fn import_jsonl(json_path: &str, db_path: &str, indexed: Option<&str>) -> io::Result<()> {
+    let jsonl = JsonlStore::open(json_path, Vec::new())?;
+    let mut docs = Vec::with_capacity(jsonl.len());
+    jsonl.scan(&mut |id, doc| docs.push((id, doc.clone())))?;
+
+    let tmp_path = format!("{}.tmp", db_path);
+    let _ = std::fs::remove_file(&tmp_path);
+    RedbStore::open_or_create(&tmp_path, indexed)?.put_all(&docs)?;
+    std::fs::rename(&tmp_path, db_path)
+}
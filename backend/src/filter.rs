@@ -0,0 +1,139 @@
+use serde::Deserialize;
+use serde_json::Value;
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::io;
+
+use crate::doc_store::DocumentStore;
+
+/// One metadata predicate of a search request, on a field of the schema,
+/// e.g. `{"field": "review_rating", "op": ">=", "value": 4}`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Filter {
+    pub field: String,
+    pub op: FilterOp,
+    pub value: Value,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum FilterOp {
+    #[serde(rename = "==")]
+    Eq,
+    #[serde(rename = "!=")]
+    Ne,
+    #[serde(rename = ">")]
+    Gt,
+    #[serde(rename = ">=")]
+    Ge,
+    #[serde(rename = "<")]
+    Lt,
+    #[serde(rename = "<=")]
+    Le,
+    #[serde(rename = "in")]
+    In,
+}
+
+impl Filter {
+    /// เช็คว่า field มีใน model และ value ใช้กับ op นี้ได้
+    pub fn validate(&self, model_fields: &[String]) -> Result<(), String> {
+        if self.field == "embedding" || !model_fields.iter().any(|f| f == &self.field) {
+            return Err(format!("unknown filter field: {}", self.field));
+        }
+        match (self.op, &self.value) {
+            (FilterOp::In, Value::Array(_)) => Ok(()),
+            (FilterOp::In, _) => Err(format!("filter on {}: `in` needs an array", self.field)),
+            (FilterOp::Gt | FilterOp::Ge | FilterOp::Lt | FilterOp::Le, v)
+                if !v.is_number() && !v.is_string() =>
+            {
+                Err(format!(
+                    "filter on {}: {:?} needs a number or string",
+                    self.field, self.op
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// document ผ่าน predicate นี้หรือไม่ (ไม่มี field = ไม่ผ่าน)
+    pub fn matches(&self, doc: &Value) -> bool {
+        let Some(actual) = doc.get(&self.field).filter(|v| !v.is_null()) else {
+            return false;
+        };
+        match self.op {
+            FilterOp::Eq => equals(actual, &self.value),
+            FilterOp::Ne => !equals(actual, &self.value),
+            FilterOp::In => self
+                .value
+                .as_array()
+                .is_some_and(|values| values.iter().any(|v| equals(actual, v))),
+            FilterOp::Gt => compare(actual, &self.value) == Some(Ordering::Greater),
+            FilterOp::Ge => matches!(
+                compare(actual, &self.value),
+                Some(Ordering::Greater | Ordering::Equal)
+            ),
+            FilterOp::Lt => compare(actual, &self.value) == Some(Ordering::Less),
+            FilterOp::Le => matches!(
+                compare(actual, &self.value),
+                Some(Ordering::Less | Ordering::Equal)
+            ),
+        }
+    }
+}
+
+/// id ของ document ที่ผ่านทุก filter
+///
+/// ถ้ามี `==` บน field ที่ store index ไว้ อ่านเฉพาะ document ของ id จาก index,
+/// ทุก filter อยู่บน field ที่ store เก็บค่าไว้ใน memory -> เช็คจากค่านั้นโดยไม่อ่าน document
+/// นอกนั้น scan ทุก document ทุกครั้ง (ดู `search` ใน config.yml)
+pub fn allowed_ids(filters: &[Filter], documents: &dyn DocumentStore) -> io::Result<HashSet<u64>> {
+    for filter in filters.iter().filter(|f| f.op == FilterOp::Eq) {
+        if let Some(ids) = documents.lookup(&filter.field, &filter.value)? {
+            let docs = documents.get_many(&ids)?;
+            return Ok(ids
+                .into_iter()
+                .zip(docs)
+                .filter(|(_, doc)| {
+                    doc.as_ref()
+                        .is_some_and(|doc| filters.iter().all(|f| f.matches(doc)))
+                })
+                .map(|(id, _)| id)
+                .collect());
+        }
+    }
+
+    let in_memory = documents.filter_fields();
+    if filters.iter().all(|f| in_memory.contains(&f.field)) {
+        let mut allowed = HashSet::new();
+        documents.scan_fields(&mut |id, values| {
+            if filters.iter().all(|f| f.matches(values)) {
+                allowed.insert(id);
+            }
+        });
+        return Ok(allowed);
+    }
+
+    let mut allowed = HashSet::new();
+    documents.scan(&mut |id, doc| {
+        if filters.iter().all(|f| f.matches(doc)) {
+            allowed.insert(id);
+        }
+    })?;
+    Ok(allowed)
+}
+
+// ตัวเลขเทียบด้วยค่า (4 == 4.0), อย่างอื่นเทียบ JSON ตรงๆ
+fn equals(a: &Value, b: &Value) -> bool {
+    match (a.as_f64(), b.as_f64()) {
+        (Some(x), Some(y)) => x == y,
+        _ => a == b,
+    }
+}
+
+// เทียบได้เฉพาะตัวเลขกับตัวเลข หรือ string กับ string
+fn compare(a: &Value, b: &Value) -> Option<Ordering> {
+    match (a, b) {
+        (Value::Number(x), Value::Number(y)) => x.as_f64()?.partial_cmp(&y.as_f64()?),
+        (Value::String(x), Value::String(y)) => Some(x.cmp(y)),
+        _ => None,
+    }
+}
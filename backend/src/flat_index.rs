@@ -1,20 +1,432 @@
 use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use half::f16;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 use std::fs::{self, File, OpenOptions};
 use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
 use std::path::Path;
 
+use crate::binary_codes::BinaryCodes;
+use crate::distance::{dot, norm, normalized, sort_hits, Metric, TopK};
+use crate::template::{version_of, LEGACY_TEMPLATE};
+use crate::vector_index::{CompactReport, SearchParams};
+
 const MAGIC: &[u8; 4] = b"RVIX";
-const VERSION: u32 = 1;
+// v1 = f32 เท่านั้น, header แค่ magic + ver + dim
+// v2 = เพิ่ม precision (+ min/max ต่อ dim สำหรับ int8)
+// v3 = เพิ่ม CRC32 ท้าย header และท้ายทุก record (crc ของ id + payload)
+// v4 = เพิ่ม metric, flag normalize และชื่อ embedding model
+// v5 = เพิ่ม version ของ embedding template (CRC32 ของ template)
+const VERSION: u32 = 5;
+// กันไฟล์เสียแล้วอ่านความยาวชื่อ model เป็นค่ามหาศาล
+const MAX_MODEL_NAME: usize = 1024;
+// tombstone file (`<index>.del`): magic + ver แล้วตามด้วย id ที่ลบ (u64) ต่อกัน
+const DEL_MAGIC: &[u8; 4] = b"RVDL";
+const DEL_VERSION: u32 = 1;
+// จำนวน record ต่อ 1 งานของ search pool
+const SCAN_CHUNK: usize = 16_384;
+
+// index ที่ใช้กับ model/dim/metric ปัจจุบันไม่ได้ -> embed ทุก document ลง index ใหม่
+const REBUILD_HINT: &str =
+    "run `backend reindex <collection> --rebuild` to re-embed its documents into a fresh index";
+
+/// How each vector component is stored in the RVIX file.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Precision {
+    #[default]
+    F32,
+    F16,
+    /// 1 byte ต่อค่า, map เชิงเส้นจากช่วง [min, max] ของแต่ละ dim
+    Int8,
+}
+
+impl Precision {
+    fn code(self) -> u32 {
+        match self {
+            Precision::F32 => 0,
+            Precision::F16 => 1,
+            Precision::Int8 => 2,
+        }
+    }
+
+    fn from_code(code: u32) -> Option<Self> {
+        match code {
+            0 => Some(Precision::F32),
+            1 => Some(Precision::F16),
+            2 => Some(Precision::Int8),
+            _ => None,
+        }
+    }
+
+    fn bytes_per_value(self) -> usize {
+        match self {
+            Precision::F32 => 4,
+            Precision::F16 => 2,
+            Precision::Int8 => 1,
+        }
+    }
+}
+
+/// What the vectors in an RVIX file are. Written to the header (v4) and
+/// checked on every open, so an index is never read with another model.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VectorSpec {
+    pub dim: usize,
+    pub precision: Precision,
+    pub metric: Metric,
+    /// model ที่ใช้ embed (เช่น `Qdrant/all-MiniLM-L6-v2-onnx`)
+    pub model: String,
+    /// version ของ embedding template ที่ใช้สร้างข้อความก่อน embed
+    pub template: u32,
+}
 
 #[derive(Debug, Clone)]
-pub struct FlatIndex {
+struct Header {
+    version: u32,
+    dim: usize,
+    precision: Precision,
+    // v4 ขึ้นไป (ไฟล์เก่า = cosine, ไม่ normalize, model ว่าง)
+    metric: Metric,
+    normalized: bool,
+    model: String,
+    // v5 ขึ้นไป (ไฟล์เก่า = template ก่อนมี template คือ review_body อย่างเดียว)
+    template: u32,
+    // int8 เท่านั้น: ช่วง [min, max] และ step ของแต่ละ dim
+    mins: Vec<f32>,
+    maxs: Vec<f32>,
+    scales: Vec<f32>,
+}
+
+impl Header {
+    // int8: ช่วง [min, max] ตั้งตอน convert จาก vector ที่มีเท่านั้น (index ว่าง calibrate ไม่ได้)
+    fn new(dim: usize, precision: Precision) -> Self {
+        Self {
+            version: VERSION,
+            dim,
+            precision,
+            metric: Metric::Cosine,
+            normalized: false,
+            model: String::new(),
+            template: version_of(LEGACY_TEMPLATE),
+            mins: Vec::new(),
+            maxs: Vec::new(),
+            scales: Vec::new(),
+        }
+    }
+
+    fn for_spec(spec: &VectorSpec) -> Self {
+        Self {
+            metric: spec.metric,
+            normalized: spec.metric.normalizes(),
+            model: spec.model.clone(),
+            template: spec.template,
+            ..Self::new(spec.dim, spec.precision)
+        }
+    }
+
+    fn with_range(mut self, mins: Vec<f32>, maxs: Vec<f32>) -> Self {
+        self.scales = mins
+            .iter()
+            .zip(&maxs)
+            .map(|(lo, hi)| (hi - lo) / 255.0)
+            .collect();
+        self.mins = mins;
+        self.maxs = maxs;
+        self
+    }
+
+    fn read(r: &mut impl Read) -> io::Result<Self> {
+        let (header, crc_ok) = Self::read_unchecked(r)?;
+        if !crc_ok {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "index header checksum mismatch (run `backend verify-index --repair`)",
+            ));
+        }
+        Ok(header)
+    }
+
+    /// อ่าน header แล้วคืนผลเช็ค CRC แยกไว้ (ไฟล์ก่อน v3 ไม่มี CRC = ผ่านเสมอ)
+    fn read_unchecked(r: &mut impl Read) -> io::Result<(Self, bool)> {
+        let mut magic = [0u8; 4];
+        r.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "bad index magic",
+            ));
+        }
+        let version = r.read_u32::<LittleEndian>()?;
+        if !(1..=VERSION).contains(&version) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "unsupported index version",
+            ));
+        }
+        let dim = r.read_u32::<LittleEndian>()? as usize;
+        if version == 1 {
+            let header = Self {
+                version,
+                ..Self::new(dim, Precision::F32)
+            };
+            return Ok((header, true));
+        }
+
+        let precision = Precision::from_code(r.read_u32::<LittleEndian>()?)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "unknown index precision"))?;
+        let mut header = Self::new(dim, precision);
+        header.version = version;
+        if version >= 4 {
+            header.metric = Metric::from_code(r.read_u32::<LittleEndian>()?).ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidData, "unknown index metric")
+            })?;
+            header.normalized = r.read_u32::<LittleEndian>()? != 0;
+            let model_len = r.read_u32::<LittleEndian>()? as usize;
+            if model_len > MAX_MODEL_NAME {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "bad index model name",
+                ));
+            }
+            let mut model = vec![0u8; model_len];
+            r.read_exact(&mut model)?;
+            header.model = String::from_utf8(model)
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "bad index model name"))?;
+        }
+        if version >= 5 {
+            header.template = r.read_u32::<LittleEndian>()?;
+        }
+        if precision == Precision::Int8 {
+            let mut mins = vec![0f32; dim];
+            let mut maxs = vec![0f32; dim];
+            r.read_f32_into::<LittleEndian>(&mut mins)?;
+            r.read_f32_into::<LittleEndian>(&mut maxs)?;
+            header = header.with_range(mins, maxs);
+        }
+
+        if version < 3 {
+            return Ok((header, true));
+        }
+        // field ทุกตัว encode กลับได้ตรง byte -> คำนวณ CRC จากที่ encode ใหม่
+        let crc = r.read_u32::<LittleEndian>()?;
+        let crc_ok = crc32fast::hash(&header.fields()?) == crc;
+        Ok((header, crc_ok))
+    }
+
+    fn write(&self, w: &mut impl Write) -> io::Result<()> {
+        let fields = self.fields()?;
+        w.write_all(&fields)?;
+        if self.has_checksums() {
+            w.write_u32::<LittleEndian>(crc32fast::hash(&fields))?;
+        }
+        Ok(())
+    }
+
+    /// byte ของ header ทั้งหมดยกเว้น CRC
+    fn fields(&self) -> io::Result<Vec<u8>> {
+        let mut w = Vec::new();
+        w.write_all(MAGIC)?;
+        w.write_u32::<LittleEndian>(self.version)?;
+        w.write_u32::<LittleEndian>(self.dim as u32)?;
+        if self.version == 1 {
+            return Ok(w);
+        }
+        w.write_u32::<LittleEndian>(self.precision.code())?;
+        if self.version >= 4 {
+            w.write_u32::<LittleEndian>(self.metric.code())?;
+            w.write_u32::<LittleEndian>(self.normalized as u32)?;
+            w.write_u32::<LittleEndian>(self.model.len() as u32)?;
+            w.write_all(self.model.as_bytes())?;
+        }
+        if self.version >= 5 {
+            w.write_u32::<LittleEndian>(self.template)?;
+        }
+        if self.precision == Precision::Int8 {
+            for &lo in &self.mins {
+                w.write_f32::<LittleEndian>(lo)?;
+            }
+            for &hi in &self.maxs {
+                w.write_f32::<LittleEndian>(hi)?;
+            }
+        }
+        Ok(w)
+    }
+
+    fn has_checksums(&self) -> bool {
+        self.version >= 3
+    }
+
+    fn len(&self) -> u64 {
+        if self.version == 1 {
+            return 12;
+        }
+        let mut len = 16;
+        if self.version >= 4 {
+            len += 12 + self.model.len() as u64;
+        }
+        if self.version >= 5 {
+            len += 4;
+        }
+        if self.precision == Precision::Int8 {
+            len += self.dim as u64 * 8;
+        }
+        if self.has_checksums() {
+            len += 4;
+        }
+        len
+    }
+
+    fn payload_bytes(&self) -> usize {
+        self.dim * self.precision.bytes_per_value()
+    }
+
+    fn record_bytes(&self) -> u64 {
+        let crc = if self.has_checksums() { 4 } else { 0 };
+        8 + self.payload_bytes() as u64 + crc
+    }
+
+    /// id + payload (+ CRC ตั้งแต่ v3) ของ record 1 ตัว
+    fn encode_record(&self, id: u64, vec: &[f32]) -> io::Result<Vec<u8>> {
+        let mut payload = Vec::with_capacity(self.payload_bytes());
+        self.encode(vec, &mut payload)?;
+        self.raw_record(id, &payload)
+    }
+
+    fn raw_record(&self, id: u64, payload: &[u8]) -> io::Result<Vec<u8>> {
+        let mut record = Vec::with_capacity(self.record_bytes() as usize);
+        record.write_u64::<LittleEndian>(id)?;
+        record.write_all(payload)?;
+        if self.has_checksums() {
+            let crc = crc32fast::hash(&record);
+            record.write_u32::<LittleEndian>(crc)?;
+        }
+        Ok(record)
+    }
+
+    /// แยก record เป็น (id, payload) แล้วเช็ค CRC (None = CRC ไม่ตรง)
+    fn split_record<'a>(&self, record: &'a [u8]) -> Option<(u64, &'a [u8])> {
+        let body = 8 + self.payload_bytes();
+        if self.has_checksums() {
+            let stored = u32::from_le_bytes(record[body..body + 4].try_into().ok()?);
+            if crc32fast::hash(&record[..body]) != stored {
+                return None;
+            }
+        }
+        let id = u64::from_le_bytes(record[..8].try_into().ok()?);
+        Some((id, &record[8..body]))
+    }
+
+    fn encode(&self, vec: &[f32], w: &mut impl Write) -> io::Result<()> {
+        let unit;
+        let vec = if self.normalized {
+            unit = normalized(vec);
+            &unit
+        } else {
+            vec
+        };
+        match self.precision {
+            Precision::F32 => {
+                for &v in vec {
+                    w.write_f32::<LittleEndian>(v)?;
+                }
+            }
+            Precision::F16 => {
+                for &v in vec {
+                    w.write_u16::<LittleEndian>(f16::from_f32(v).to_bits())?;
+                }
+            }
+            Precision::Int8 => {
+                for ((&v, lo), step) in vec.iter().zip(&self.mins).zip(&self.scales) {
+                    let q = if *step > 0.0 {
+                        ((v - lo) / step).round().clamp(0.0, 255.0) as u8
+                    } else {
+                        0
+                    };
+                    w.write_u8(q)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn decode(&self, payload: &[u8], out: &mut [f32]) {
+        match self.precision {
+            Precision::F32 => {
+                for (o, b) in out.iter_mut().zip(payload.chunks_exact(4)) {
+                    *o = f32::from_le_bytes([b[0], b[1], b[2], b[3]]);
+                }
+            }
+            Precision::F16 => {
+                for (o, b) in out.iter_mut().zip(payload.chunks_exact(2)) {
+                    *o = f16::from_le_bytes([b[0], b[1]]).to_f32();
+                }
+            }
+            Precision::Int8 => {
+                for (i, (o, &q)) in out.iter_mut().zip(payload).enumerate() {
+                    *o = self.mins[i] + q as f32 * self.scales[i];
+                }
+            }
+        }
+    }
+}
+
+/// Result of `RvixFile::verify`: what is wrong with an RVIX file, if anything.
+#[derive(Debug, Serialize)]
+pub struct VerifyReport {
+    pub version: u32,
+    pub checksums: bool,
+    pub header_ok: bool,
+    pub records: usize,
+    pub corrupt: Vec<RecordIssue>,
+    pub non_monotonic: Vec<RecordIssue>,
+    pub trailing_bytes: u64,
+    pub meta_next_id: Option<u64>,
+    pub expected_next_id: u64,
+    pub ok: bool,
+    pub repaired: bool,
+    pub records_dropped: usize,
+}
+
+/// A record flagged by `verify`, by slot (position in the file) and id as read.
+#[derive(Debug, Serialize)]
+pub struct RecordIssue {
+    pub slot: usize,
+    pub id: u64,
+}
+
+// ช่วงของ int8 calibrate จาก vector ที่มีอยู่ -> index ว่างใช้ int8 ไม่ได้
+fn int8_needs_vectors(index_path: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!(
+            "{} has no vectors to calibrate int8 precision from; \
+             build it with f32 or f16 first, then switch index.precision to int8",
+            index_path
+        ),
+    )
+}
+
+/// Record storage of an RVIX file: header, appends and sequential/random reads.
+///
+/// Only the tombstones are kept in memory; `FlatIndex` builds its in-memory
+/// rows on top of it, and `PqIndex` uses it directly so full vectors stay on
+/// disk. Deleted ids are appended to `<index>.del` and dropped from the RVIX
+/// file itself only by `compact`.
+#[derive(Debug, Clone)]
+pub struct RvixFile {
     index_path: String,
     meta_path: String,
+    del_path: String,
     dim: usize,
+    header: Header,
+    deleted: HashSet<u64>,
 }
 
-impl FlatIndex {
-    pub fn open_or_create(index_path: impl Into<String>, dim: usize) -> io::Result<Self> {
+impl RvixFile {
+    /// เปิด/สร้างไฟล์ตาม `spec`; ถ้าไฟล์เดิมเก็บคนละ precision หรือเป็น format เก่าจะ convert ให้
+    pub fn open_or_create(index_path: impl Into<String>, spec: &VectorSpec) -> io::Result<Self> {
         let index_path = index_path.into();
         let meta_path = format!("{}.meta", index_path);
 
@@ -25,55 +437,94 @@ impl FlatIndex {
             }
         }
 
-        // create file if not exist + write header
-        if !Path::new(&index_path).exists() {
-            let mut f = File::create(&index_path)?;
-            f.write_all(MAGIC)?;
-            f.write_u32::<LittleEndian>(VERSION)?;
-            f.write_u32::<LittleEndian>(dim as u32)?;
-            f.flush()?;
+        // create file if not exist + write header (tmp แล้ว rename กัน header ครึ่งๆ)
+        let header = if !Path::new(&index_path).exists() {
+            if spec.precision == Precision::Int8 {
+                return Err(int8_needs_vectors(&index_path));
+            }
+            let header = Header::for_spec(spec);
+            let tmp_path = format!("{}.tmp", index_path);
+            {
+                let mut f = File::create(&tmp_path)?;
+                header.write(&mut f)?;
+                f.sync_all()?;
+            }
+            fs::rename(&tmp_path, &index_path)?;
 
             // init meta
             if !Path::new(&meta_path).exists() {
                 Self::write_next_id(&meta_path, 1)?;
             }
+            header
         } else {
-            // validate header
+            // validate header: model ก่อน (model เปลี่ยน = dim มักเปลี่ยนด้วย บอกสาเหตุจริง)
             let mut f = File::open(&index_path)?;
-            let mut magic = [0u8; 4];
-            f.read_exact(&mut magic)?;
-            if &magic != MAGIC {
+            let mut header = Header::read(&mut f)?;
+            // ไฟล์ก่อน v4 ไม่ได้บันทึก model -> ถือว่าเป็น model ปัจจุบันแล้ว migrate ด้านล่าง
+            if header.version >= 4 && header.model != spec.model {
                 return Err(io::Error::new(
                     io::ErrorKind::InvalidData,
-                    "bad index magic",
+                    format!(
+                        "{} was built with embedding model `{}` but the collection uses `{}`; {}",
+                        index_path, header.model, spec.model, REBUILD_HINT
+                    ),
                 ));
             }
-            let ver = f.read_u32::<LittleEndian>()?;
-            if ver != VERSION {
+            if header.dim != spec.dim {
                 return Err(io::Error::new(
                     io::ErrorKind::InvalidData,
-                    "unsupported index version",
+                    format!(
+                        "{} holds {}-dim vectors but the embedding model makes {}-dim ones; {}",
+                        index_path, header.dim, spec.dim, REBUILD_HINT
+                    ),
                 ));
             }
-            let file_dim = f.read_u32::<LittleEndian>()? as usize;
-            if file_dim != dim {
+            if header.version >= 4 && header.metric != spec.metric {
                 return Err(io::Error::new(
                     io::ErrorKind::InvalidData,
-                    "dim mismatch with existing index",
+                    format!(
+                        "{} was built for metric {:?} but the collection uses {:?}; {}",
+                        index_path, header.metric, spec.metric, REBUILD_HINT
+                    ),
                 ));
             }
 
-            if !Path::new(&meta_path).exists() {
-                // if meta missing, rebuild next_id by scanning record count
-                let next_id = Self::scan_next_id(&index_path, dim)?;
+            // crash ระหว่างเขียนทับ slot -> ใส่ record เดิมจาก journal คืน
+            Self::recover_journal(&index_path, &header)?;
+
+            // crash ระหว่าง append -> ตัด record ที่เขียนไม่ครบทิ้ง
+            let last_id = Self::recover_tail(&index_path, &header)?;
+
+            // meta อาจหาย/เขียนไม่ครบ/ยังไม่ได้ bump หลัง record ล่าสุด
+            // -> next_id ต้องมากกว่า id ล่าสุดในไฟล์เสมอ (กัน id ซ้ำ)
+            let stored = Self::read_next_id(&meta_path).ok();
+            let next_id = match (stored, last_id) {
+                (Some(n), Some(last)) => n.max(last + 1),
+                (Some(n), None) => n,
+                (None, Some(last)) => last + 1,
+                (None, None) => 1,
+            };
+            if stored != Some(next_id) {
                 Self::write_next_id(&meta_path, next_id)?;
             }
-        }
+
+            // precision เปลี่ยน หรือไฟล์ format เก่า -> เขียนใหม่เป็น format ปัจจุบัน
+            if header.precision != spec.precision || header.version != VERSION {
+                header = Self::convert(&index_path, &header, spec)?;
+            }
+            header
+        };
+
+        let del_path = format!("{}.del", index_path);
+        let deleted = Self::read_deleted(&del_path)?;
 
         Ok(Self {
             index_path,
             meta_path,
-            dim,
+            del_path,
+            dim: spec.dim,
+            header,
+            deleted,
         })
     }
 
@@ -81,6 +532,32 @@ impl FlatIndex {
         self.dim
     }
 
+    pub fn path(&self) -> &str {
+        &self.index_path
+    }
+
+    pub fn metric(&self) -> Metric {
+        self.header.metric
+    }
+
+    /// version ของ embedding template ที่ vector ในไฟล์ถูกสร้างมา
+    pub fn template(&self) -> u32 {
+        self.header.template
+    }
+
+    /// บันทึก template version ใหม่หลัง reindex (header ยาวเท่าเดิม -> เขียนทับที่ต้นไฟล์)
+    pub fn set_template(&mut self, template: u32) -> io::Result<()> {
+        let mut header = self.header.clone();
+        header.template = template;
+
+        let mut f = OpenOptions::new().write(true).open(&self.index_path)?;
+        header.write(&mut f)?;
+        f.sync_data()?;
+
+        self.header = header;
+        Ok(())
+    }
+
     pub fn append(&self, vec: &[f32]) -> io::Result<u64> {
         if vec.len() != self.dim {
             return Err(io::Error::new(
@@ -91,18 +568,17 @@ impl FlatIndex {
 
         let id = Self::read_next_id(&self.meta_path)?;
 
-        // append record
-        let f = OpenOptions::new()
+        // append record ด้วย write ครั้งเดียวแล้ว fsync ก่อน bump id:
+        // crash ตรงไหนก็ตาม open_or_create จะตัด record ครึ่งๆ ทิ้ง
+        // และเลื่อน next_id ให้เลย id ล่าสุดในไฟล์เอง
+        let record = self.header.encode_record(id, vec)?;
+
+        let mut f = OpenOptions::new()
             .create(true)
             .append(true)
             .open(&self.index_path)?;
-        let mut w = BufWriter::new(f);
-
-        w.write_u64::<LittleEndian>(id)?;
-        for &v in vec {
-            w.write_f32::<LittleEndian>(v)?;
-        }
-        w.flush()?;
+        f.write_all(&record)?;
+        f.sync_data()?;
 
         // bump id
         Self::write_next_id(&self.meta_path, id + 1)?;
@@ -110,74 +586,427 @@ impl FlatIndex {
         Ok(id)
     }
 
-    pub fn search(&self, query: &[f32], top_k: usize) -> io::Result<Vec<(u64, f32)>> {
-        if query.len() != self.dim {
+    /// เขียนทับ vector ของ record ที่ `slot` (record ขนาดคงที่ -> เขียนทับได้เลย)
+    pub fn write_slot(&self, slot: usize, id: u64, vec: &[f32]) -> io::Result<()> {
+        if vec.len() != self.dim {
             return Err(io::Error::new(
                 io::ErrorKind::InvalidInput,
                 "dimension mismatch",
             ));
         }
-        if top_k == 0 {
-            return Ok(vec![]);
-        }
 
-        let f = File::open(&self.index_path)?;
-        let mut r = BufReader::new(f);
+        let record = self.header.encode_record(id, vec)?;
+        let offset = self.header.len() + slot as u64 * self.header.record_bytes();
+
+        let mut f = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&self.index_path)?;
+        let mut old = vec![0u8; record.len()];
+        f.seek(SeekFrom::Start(offset))?;
+        f.read_exact(&mut old)?;
+
+        // journal record เดิมลง disk ก่อนเขียนทับ: crash ระหว่างนี้ = ได้ record เดิมคืนตอน open
+        let mut journal = Self::open_journal(&self.index_path)?;
+        let mut entry = Vec::with_capacity(old.len() + 12);
+        entry.write_u64::<LittleEndian>(slot as u64)?;
+        entry.extend_from_slice(&old);
+        let crc = crc32fast::hash(&entry);
+        entry.write_u32::<LittleEndian>(crc)?;
+        journal.write_all(&entry)?;
+        journal.sync_data()?;
 
-        // skip header: magic(4) + ver(4) + dim(4)
-        r.seek(SeekFrom::Start(12))?;
+        f.seek(SeekFrom::Start(offset))?;
+        f.write_all(&record)?;
+        f.sync_data()?;
+
+        journal.set_len(0)?;
+        journal.sync_data()
+    }
+
+    // journal ของ write_slot (ไฟล์ว่าง = ไม่มีงานค้าง) สร้างครั้งแรก -> sync directory ด้วย
+    fn open_journal(index_path: &str) -> io::Result<File> {
+        let path = format!("{}.journal", index_path);
+        let new_file = !Path::new(&path).exists();
+        let f = OpenOptions::new()
+            .create(true)
+            .truncate(true)
+            .write(true)
+            .open(&path)?;
+        if new_file {
+            if let Some(parent) = Path::new(&path).parent() {
+                let parent = if parent.as_os_str().is_empty() {
+                    Path::new(".")
+                } else {
+                    parent
+                };
+                File::open(parent)?.sync_all()?;
+            }
+        }
+        Ok(f)
+    }
 
-        // cosine helpers
-        fn dot(a: &[f32], b: &[f32]) -> f32 {
-            a.iter().zip(b.iter()).map(|(x, y)| x * y).sum()
+    /// journal ที่ครบและ CRC ตรง = เขียนทับ slot ไม่จบ -> เขียน record เดิมกลับ
+    /// journal ไม่ครบ = crash ก่อนเริ่มเขียนทับ -> ทิ้งได้เลย
+    fn recover_journal(index_path: &str, header: &Header) -> io::Result<()> {
+        let path = format!("{}.journal", index_path);
+        let entry = match fs::read(&path) {
+            Ok(entry) => entry,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        if entry.is_empty() {
+            return Ok(());
         }
-        fn norm(a: &[f32]) -> f32 {
-            dot(a, a).sqrt()
+
+        let body = entry.len().saturating_sub(4);
+        let complete = entry.len() == 8 + header.record_bytes() as usize + 4
+            && crc32fast::hash(&entry[..body])
+                == u32::from_le_bytes(entry[body..].try_into().unwrap());
+        if complete {
+            let slot = u64::from_le_bytes(entry[..8].try_into().unwrap());
+            let mut f = OpenOptions::new().write(true).open(index_path)?;
+            f.seek(SeekFrom::Start(header.len() + slot * header.record_bytes()))?;
+            f.write_all(&entry[8..body])?;
+            f.sync_data()?;
         }
-        fn cosine_distance(a: &[f32], b: &[f32]) -> f32 {
-            let na = norm(a);
-            let nb = norm(b);
-            if na == 0.0 || nb == 0.0 {
-                1.0
-            } else {
-                let sim = dot(a, b) / (na * nb);
-                1.0 - sim
-            }
+
+        let journal = OpenOptions::new().write(true).open(&path)?;
+        journal.set_len(0)?;
+        journal.sync_data()
+    }
+
+    /// อ่านทุก record ตามลำดับในไฟล์ แล้วเรียก `f(id, vec)` (decode เป็น f32 แล้ว)
+    pub fn for_each(&self, mut f: impl FnMut(u64, &[f32])) -> io::Result<()> {
+        let mut vec = vec![0f32; self.dim];
+        self.for_each_raw(|id, payload| {
+            self.header.decode(payload, &mut vec);
+            f(id, &vec);
+        })
+    }
+
+    /// อ่าน record ตามตำแหน่งในไฟล์ (slot เริ่มที่ 0)
+    pub fn read_slots(&self, slots: &[usize]) -> io::Result<Vec<(u64, Vec<f32>)>> {
+        let mut f = File::open(&self.index_path)?;
+        let record_bytes = self.header.record_bytes();
+        let mut record = vec![0u8; record_bytes as usize];
+
+        let mut out = Vec::with_capacity(slots.len());
+        for &slot in slots {
+            f.seek(SeekFrom::Start(
+                self.header.len() + slot as u64 * record_bytes,
+            ))?;
+            f.read_exact(&mut record)?;
+            let (id, payload) = self
+                .header
+                .split_record(&record)
+                .ok_or_else(|| corrupt_record(slot))?;
+            let mut vec = vec![0f32; self.dim];
+            self.header.decode(payload, &mut vec);
+            out.push((id, vec));
         }
 
-        // maintain top_k by distance asc
-        let mut best: Vec<(u64, f32)> = Vec::with_capacity(top_k);
+        Ok(out)
+    }
+
+    /// อ่าน vector ตาม slot ที่มี (None = ไม่มี record) คงลำดับเดิม
+    pub fn read_vectors(&self, slots: &[Option<usize>]) -> io::Result<Vec<Option<Vec<f32>>>> {
+        let found: Vec<usize> = slots.iter().flatten().copied().collect();
+        let mut read = self.read_slots(&found)?.into_iter();
+        Ok(slots
+            .iter()
+            .map(|slot| slot.and_then(|_| read.next()).map(|(_, vec)| vec))
+            .collect())
+    }
+
+    fn for_each_raw(&self, mut f: impl FnMut(u64, &[u8])) -> io::Result<()> {
+        let file = File::open(&self.index_path)?;
+        let mut r = BufReader::new(file);
 
+        // skip header
+        r.seek(SeekFrom::Start(self.header.len()))?;
+
+        let mut record = vec![0u8; self.header.record_bytes() as usize];
+        let mut slot = 0usize;
         loop {
-            // read id
-            let id = match r.read_u64::<LittleEndian>() {
-                Ok(v) => v,
+            // record ไม่ครบท้ายไฟล์ถูกตัดทิ้งตอน open แล้ว -> EOF = จบไฟล์
+            match r.read_exact(&mut record) {
+                Ok(()) => {}
                 Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
                 Err(e) => return Err(e),
-            };
+            }
 
-            // read vec
-            let mut vec = vec![0f32; self.dim];
-            for i in 0..self.dim {
-                vec[i] = r.read_f32::<LittleEndian>()?;
+            let (id, payload) = self
+                .header
+                .split_record(&record)
+                .ok_or_else(|| corrupt_record(slot))?;
+            f(id, payload);
+            slot += 1;
+        }
+
+        Ok(())
+    }
+
+    /// เขียน index ใหม่ทั้งไฟล์ด้วย precision ใหม่ (int8 จะ calibrate min/max จาก vector ที่มี)
+    fn convert(index_path: &str, old: &Header, spec: &VectorSpec) -> io::Result<Header> {
+        let old_file = RvixFile {
+            index_path: index_path.to_string(),
+            meta_path: String::new(),
+            del_path: String::new(),
+            dim: old.dim,
+            header: old.clone(),
+            deleted: HashSet::new(),
+        };
+
+        // vector เดิมยังเป็นของ template เดิม -> คง version เดิมไว้ (เปลี่ยนได้ด้วย reindex)
+        let mut header = Header {
+            template: old.template,
+            ..Header::for_spec(spec)
+        };
+        if spec.precision == Precision::Int8 {
+            let mut mins = vec![f32::INFINITY; old.dim];
+            let mut maxs = vec![f32::NEG_INFINITY; old.dim];
+            old_file.for_each(|_, vec| {
+                // calibrate จากค่าที่จะเก็บจริง (normalize แล้วถ้า metric ต้องการ)
+                let vec = if header.normalized {
+                    normalized(vec)
+                } else {
+                    vec.to_vec()
+                };
+                for (i, &v) in vec.iter().enumerate() {
+                    mins[i] = mins[i].min(v);
+                    maxs[i] = maxs[i].max(v);
+                }
+            })?;
+            if !mins.iter().all(|v| v.is_finite()) {
+                return Err(int8_needs_vectors(index_path));
             }
+            header = header.with_range(mins, maxs);
+        }
 
-            let dist = cosine_distance(query, &vec);
+        let tmp_path = format!("{}.tmp", index_path);
+        {
+            let f = File::create(&tmp_path)?;
+            let mut w = BufWriter::new(f);
+            header.write(&mut w)?;
 
-            // insert into best
-            if best.len() < top_k {
-                best.push((id, dist));
-                best.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal));
-            } else if let Some(&(_, worst_dist)) = best.last() {
-                if dist < worst_dist {
-                    best.pop();
-                    best.push((id, dist));
-                    best.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal));
+            let mut result = Ok(());
+            old_file.for_each(|id, vec| {
+                if result.is_ok() {
+                    result = header
+                        .encode_record(id, vec)
+                        .and_then(|record| w.write_all(&record));
                 }
+            })?;
+            result?;
+
+            w.flush()?;
+            w.get_ref().sync_all()?;
+        }
+        fs::rename(&tmp_path, index_path)?;
+
+        Ok(header)
+    }
+
+    pub fn is_deleted(&self, id: u64) -> bool {
+        self.deleted.contains(&id)
+    }
+
+    /// เขียน tombstone ของ `id` (ไม่เช็คว่ามี id นี้ในไฟล์หรือเปล่า ให้คนเรียกเช็คเอง)
+    pub fn mark_deleted(&mut self, id: u64) -> io::Result<()> {
+        let new_file = !Path::new(&self.del_path).exists();
+        let f = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.del_path)?;
+        let mut w = BufWriter::new(f);
+        if new_file {
+            w.write_all(DEL_MAGIC)?;
+            w.write_u32::<LittleEndian>(DEL_VERSION)?;
+        }
+        w.write_u64::<LittleEndian>(id)?;
+        w.flush()?;
+        // tombstone ต้องลง disk ก่อนตอบว่าลบแล้ว
+        w.get_ref().sync_data()?;
+
+        self.deleted.insert(id);
+        Ok(())
+    }
+
+    /// เขียน RVIX ใหม่โดยตัด record ที่ถูกลบออก แล้วล้าง tombstone
+    pub fn compact(&mut self) -> io::Result<CompactReport> {
+        if self.deleted.is_empty() {
+            return Ok(CompactReport::default());
+        }
+
+        let before = file_len(&self.index_path)? + file_len(&self.del_path)?;
+
+        let tmp_path = format!("{}.tmp", self.index_path);
+        let mut removed = 0usize;
+        {
+            let f = File::create(&tmp_path)?;
+            let mut w = BufWriter::new(f);
+            self.header.write(&mut w)?;
+
+            let mut result = Ok(());
+            self.for_each_raw(|id, payload| {
+                if self.deleted.contains(&id) {
+                    removed += 1;
+                } else if result.is_ok() {
+                    result = self
+                        .header
+                        .raw_record(id, payload)
+                        .and_then(|record| w.write_all(&record));
+                }
+            })?;
+            result?;
+
+            w.flush()?;
+            w.get_ref().sync_all()?;
+        }
+        fs::rename(&tmp_path, &self.index_path)?;
+
+        // next_id ไม่เปลี่ยน: id ที่ลบไปแล้วจะไม่ถูกใช้ซ้ำ
+        if Path::new(&self.del_path).exists() {
+            fs::remove_file(&self.del_path)?;
+        }
+        self.deleted.clear();
+
+        let after = file_len(&self.index_path)?;
+        Ok(CompactReport {
+            records_removed: removed,
+            bytes_reclaimed: before.saturating_sub(after),
+        })
+    }
+
+    /// ตรวจ `index_path` ทั้งไฟล์โดยไม่แก้อะไร (ยกเว้น `repair`): CRC ของ header/record,
+    /// id ที่ไม่เพิ่มขึ้นเรื่อยๆ, byte เกินท้ายไฟล์ และ next_id ใน meta ที่น้อยกว่า id ในไฟล์
+    ///
+    /// `repair` = เขียนไฟล์ใหม่ (อย่างน้อย v3 ให้มี CRC) เฉพาะ record ที่ CRC ถูกและ id เพิ่มขึ้น
+    /// แล้วแก้ meta ให้ตรง (ไฟล์ sidecar ต่างๆ จะ rebuild เองตอน open ครั้งถัดไป)
+    pub fn verify(index_path: &str, dim: usize, repair: bool) -> io::Result<VerifyReport> {
+        let meta_path = format!("{}.meta", index_path);
+
+        let f = File::open(index_path)?;
+        let len = f.metadata()?.len();
+        let mut r = BufReader::new(f);
+        let (header, header_ok) = Header::read_unchecked(&mut r)?;
+        if header.dim != dim {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "dim mismatch with existing index",
+            ));
+        }
+
+        let record_bytes = header.record_bytes();
+        let records = (len.saturating_sub(header.len()) / record_bytes) as usize;
+        let trailing_bytes = len.saturating_sub(header.len()) % record_bytes;
+
+        let mut corrupt = Vec::new();
+        let mut non_monotonic = Vec::new();
+        // record ที่ผ่านทั้งหมด (เก็บไว้เขียนใหม่ตอน repair)
+        let mut good: Vec<(u64, Vec<u8>)> = Vec::new();
+        let mut last_id: Option<u64> = None;
+        let mut record = vec![0u8; record_bytes as usize];
+        for slot in 0..records {
+            r.read_exact(&mut record)?;
+            let Some((id, payload)) = header.split_record(&record) else {
+                let id = u64::from_le_bytes(record[..8].try_into().unwrap());
+                corrupt.push(RecordIssue { slot, id });
+                continue;
+            };
+            if last_id.is_some_and(|last| id <= last) {
+                non_monotonic.push(RecordIssue { slot, id });
+                continue;
+            }
+            last_id = Some(id);
+            if repair {
+                good.push((id, payload.to_vec()));
             }
         }
 
-        Ok(best)
+        let meta_next_id = Self::read_next_id(&meta_path).ok();
+        let expected_next_id = last_id.map_or(1, |last| last + 1);
+        let meta_ok = meta_next_id.is_some_and(|n| n >= expected_next_id);
+
+        let ok = header_ok
+            && corrupt.is_empty()
+            && non_monotonic.is_empty()
+            && trailing_bytes == 0
+            && meta_ok;
+
+        let mut report = VerifyReport {
+            version: header.version,
+            checksums: header.has_checksums(),
+            header_ok,
+            records,
+            corrupt,
+            non_monotonic,
+            trailing_bytes,
+            meta_next_id,
+            expected_next_id,
+            ok,
+            repaired: false,
+            records_dropped: 0,
+        };
+        if !repair || (ok && header.has_checksums()) {
+            return Ok(report);
+        }
+
+        // ไม่ upgrade เป็น v4 ตรงนี้: ยังไม่รู้ model -> ให้ open ครั้งถัดไป migrate เอง
+        let clean = Header {
+            version: header.version.max(3),
+            ..header
+        };
+        let tmp_path = format!("{}.tmp", index_path);
+        {
+            let f = File::create(&tmp_path)?;
+            let mut w = BufWriter::new(f);
+            clean.write(&mut w)?;
+            for (id, payload) in &good {
+                w.write_all(&clean.raw_record(*id, payload)?)?;
+            }
+            w.flush()?;
+            w.get_ref().sync_all()?;
+        }
+        fs::rename(&tmp_path, index_path)?;
+
+        // id ที่เคยแจกไปแล้วไม่ใช้ซ้ำ -> เอาค่าที่มากกว่า
+        let next_id = meta_next_id.map_or(expected_next_id, |n| n.max(expected_next_id));
+        Self::write_next_id(&meta_path, next_id)?;
+
+        report.repaired = true;
+        report.records_dropped = records - good.len();
+        Ok(report)
+    }
+
+    fn read_deleted(del_path: &str) -> io::Result<HashSet<u64>> {
+        let mut deleted = HashSet::new();
+        if !Path::new(del_path).exists() {
+            return Ok(deleted);
+        }
+
+        let f = File::open(del_path)?;
+        let mut r = BufReader::new(f);
+        let mut magic = [0u8; 4];
+        r.read_exact(&mut magic)?;
+        if &magic != DEL_MAGIC || r.read_u32::<LittleEndian>()? != DEL_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "bad tombstone file header",
+            ));
+        }
+        loop {
+            // id ที่เขียนไม่ครบ 8 byte = ยังลบไม่สำเร็จ -> ข้าม
+            match r.read_u64::<LittleEndian>() {
+                Ok(id) => {
+                    deleted.insert(id);
+                }
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(deleted)
     }
 
     fn read_next_id(meta_path: &str) -> io::Result<u64> {
@@ -185,7 +1014,7 @@ impl FlatIndex {
         let mut buf = [0u8; 8];
         f.read_exact(&mut buf)?;
         let mut c = io::Cursor::new(buf);
-        Ok(c.read_u64::<LittleEndian>()?)
+        c.read_u64::<LittleEndian>()
     }
 
     fn write_next_id(meta_path: &str, next_id: u64) -> io::Result<()> {
@@ -194,38 +1023,948 @@ impl FlatIndex {
                 fs::create_dir_all(parent)?;
             }
         }
-        let mut f = File::create(meta_path)?;
-        f.write_u64::<LittleEndian>(next_id)?;
-        f.flush()?;
+        // tmp แล้ว rename: meta มีแค่ค่าเก่าหรือค่าใหม่ ไม่มีไฟล์ว่าง
+        let tmp_path = format!("{}.tmp", meta_path);
+        {
+            let mut f = File::create(&tmp_path)?;
+            f.write_u64::<LittleEndian>(next_id)?;
+            f.sync_all()?;
+        }
+        fs::rename(&tmp_path, meta_path)
+    }
+
+    /// ตัด record สุดท้ายที่เขียนไม่ครบออก แล้วคืน id ของ record สุดท้าย (None = ไม่มี record)
+    fn recover_tail(index_path: &str, header: &Header) -> io::Result<Option<u64>> {
+        let len = fs::metadata(index_path)?.len();
+        let body = len.saturating_sub(header.len());
+        let complete = body / header.record_bytes();
+
+        let end = header.len() + complete * header.record_bytes();
+        if end != len {
+            let f = OpenOptions::new().write(true).open(index_path)?;
+            f.set_len(end)?;
+            f.sync_all()?;
+        }
+
+        if complete == 0 {
+            return Ok(None);
+        }
+        let mut f = File::open(index_path)?;
+        f.seek(SeekFrom::Start(end - header.record_bytes()))?;
+        Ok(Some(f.read_u64::<LittleEndian>()?))
+    }
+}
+
+/// Writes a fresh RVIX file from (id, vector) pairs and swaps it in over the
+/// existing index on `finish`; used by `backend reindex --rebuild` when the
+/// index can't be opened (other model/dim/metric) or has lost records.
+pub struct RvixBuilder {
+    index_path: String,
+    tmp_path: String,
+    header: Header,
+    w: BufWriter<File>,
+    last_id: Option<u64>,
+    records: usize,
+}
+
+impl RvixBuilder {
+    /// เริ่มเขียน `<index>.rebuild` ตาม `spec` (ไฟล์เดิมยังใช้ได้จนกว่าจะ `finish`)
+    ///
+    /// int8 ต้อง calibrate จาก vector ที่มี -> เขียนเป็น f32 ไปก่อน แล้ว open ด้วย spec
+    /// int8 ให้ convert เอง
+    pub fn create(index_path: impl Into<String>, spec: &VectorSpec) -> io::Result<Self> {
+        let index_path = index_path.into();
+        if let Some(parent) = Path::new(&index_path).parent() {
+            if !parent.as_os_str().is_empty() {
+                fs::create_dir_all(parent)?;
+            }
+        }
+
+        let precision = match spec.precision {
+            Precision::Int8 => Precision::F32,
+            p => p,
+        };
+        let header = Header::for_spec(&VectorSpec {
+            precision,
+            ..spec.clone()
+        });
+        let tmp_path = format!("{}.rebuild", index_path);
+        let mut w = BufWriter::new(File::create(&tmp_path)?);
+        header.write(&mut w)?;
+
+        Ok(Self {
+            index_path,
+            tmp_path,
+            header,
+            w,
+            last_id: None,
+            records: 0,
+        })
+    }
+
+    /// เขียน record ต่อท้าย (id ต้องเพิ่มขึ้นเรื่อยๆ เหมือนใน index ปกติ)
+    pub fn push(&mut self, id: u64, vec: &[f32]) -> io::Result<()> {
+        if vec.len() != self.header.dim {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "dimension mismatch",
+            ));
+        }
+        if self.last_id.is_some_and(|last| id <= last) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("id {} is not greater than the previous id", id),
+            ));
+        }
+        let record = self.header.encode_record(id, vec)?;
+        self.w.write_all(&record)?;
+        self.last_id = Some(id);
+        self.records += 1;
         Ok(())
     }
 
-    fn scan_next_id(index_path: &str, dim: usize) -> io::Result<u64> {
-        let f = File::open(index_path)?;
-        let mut r = BufReader::new(f);
-        r.seek(SeekFrom::Start(12))?;
+    /// sync ไฟล์ใหม่แล้ว rename ทับ index เดิม คืนจำนวน record
+    ///
+    /// journal กับ tombstone ของไฟล์เดิมอ้างถึง slot/id ของไฟล์เดิม -> ลบก่อน rename
+    /// (crash ระหว่างนั้น = เปิดไฟล์เดิมได้ตามปกติ แค่ record ที่ลบไปแล้วกลับมา)
+    pub fn finish(mut self) -> io::Result<usize> {
+        self.w.flush()?;
+        self.w.get_ref().sync_all()?;
 
-        let record_bytes = 8u64 + (dim as u64) * 4u64;
-        let mut count: u64 = 0;
+        remove_if_exists(&format!("{}.journal", self.index_path))?;
+        remove_if_exists(&format!("{}.del", self.index_path))?;
+        fs::rename(&self.tmp_path, &self.index_path)?;
 
-        loop {
-            // try read id
-            let _id = match r.read_u64::<LittleEndian>() {
-                Ok(v) => v,
-                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
-                Err(e) => return Err(e),
-            };
+        // next_id ไม่ถอยหลัง: id ของ index เดิมที่เคยออกไปแล้วไม่ถูกใช้ซ้ำ
+        let meta_path = format!("{}.meta", self.index_path);
+        let stored = RvixFile::read_next_id(&meta_path).unwrap_or(1);
+        let next_id = stored.max(self.last_id.map_or(1, |last| last + 1));
+        RvixFile::write_next_id(&meta_path, next_id)?;
+
+        Ok(self.records)
+    }
+}
+
+/// In-memory copy of every record, structure-of-arrays: ids in one Vec, the
+/// vectors back to back in another. For cosine, f32 rows are stored
+/// normalized so the distance is `1 - dot`; f16/int8 keep the values from the
+/// file (so scores match the on-disk data exactly) plus a per-row 1/norm.
+/// Other metrics use the rows as they are.
+#[derive(Debug)]
+enum Rows {
+    F32(Vec<f32>),
+    F16 {
+        values: Vec<f16>,
+        inv_norms: Vec<f32>,
+    },
+    Int8 {
+        codes: Vec<u8>,
+        inv_norms: Vec<f32>,
+    },
+}
+
+/// Query prepared once per search for the row layout of the index.
+pub struct Query {
+    // f32/f16: query ที่ normalize แล้ว, int8: query * scale ของแต่ละ dim
+    v: Vec<f32>,
+    // int8: sum(query * min)
+    base: f32,
+}
+
+#[derive(Debug)]
+pub struct FlatIndex {
+    file: RvixFile,
+    ids: Vec<u64>,
+    // slot -> ถูกลบแล้ว (มี tombstone)
+    dead: Vec<bool>,
+    rows: Rows,
+    // sign-bit prefilter (None = scan เต็มทุก record)
+    binary: Option<BinaryCodes>,
+    rescore: usize,
+}
+
+impl FlatIndex {
+    /// เปิด/สร้าง index ตาม `spec` แล้วโหลดทุก record เข้า memory
+    pub fn open_or_create(index_path: impl Into<String>, spec: &VectorSpec) -> io::Result<Self> {
+        let file = RvixFile::open_or_create(index_path, spec)?;
+        Self::load(file)
+    }
+
+    fn load(file: RvixFile) -> io::Result<Self> {
+        let dim = file.dim();
+        let mut index = Self {
+            rows: match file.header.precision {
+                Precision::F32 => Rows::F32(Vec::new()),
+                Precision::F16 => Rows::F16 {
+                    values: Vec::new(),
+                    inv_norms: Vec::new(),
+                },
+                Precision::Int8 => Rows::Int8 {
+                    codes: Vec::new(),
+                    inv_norms: Vec::new(),
+                },
+            },
+            file,
+            ids: Vec::new(),
+            dead: Vec::new(),
+            binary: None,
+            rescore: 0,
+        };
+
+        let mut vec = vec![0f32; dim];
+        let file = index.file.clone();
+        file.for_each_raw(|id, payload| {
+            file.header.decode(payload, &mut vec);
+            index.push_row(id, &vec, payload);
+        })?;
+        index.dead = index.ids.iter().map(|&id| file.is_deleted(id)).collect();
+
+        Ok(index)
+    }
+
+    /// เปิด binary prefilter: เก็บ sign-bit ของทุก vector ไว้ที่ `<index>.bq`,
+    /// search จะ scan ด้วย Hamming ก่อนแล้ว rescore `rescore` ตัวแรกด้วย metric ของ index
+    pub fn with_binary_prefilter(mut self, rescore: usize) -> io::Result<Self> {
+        let mut codes =
+            BinaryCodes::open_or_create(format!("{}.bq", self.file.path()), self.dim())?;
+
+        // sync กับ RVIX: เก็บ code ของ slot ที่ id ตรงกัน ตัดตั้งแต่ slot แรกที่ไม่ตรง
+        // (เช่น compact ตอนปิด binary) แล้ว build ใหม่ตั้งแต่ slot นั้น
+        let have = codes
+            .ids()
+            .iter()
+            .zip(&self.ids)
+            .take_while(|(code_id, id)| code_id == id)
+            .count();
+        codes.truncate(have)?;
+        // code ที่ id ตรงแต่ vector เปลี่ยน (replace ตอนปิด binary หรือ replace ที่หายตอน crash)
+        // -> เทียบ sign-bit กับ row ใน memory แล้วเขียน slot ที่ไม่ตรงใหม่
+        let stale: Vec<usize> = (0..have)
+            .into_par_iter()
+            .filter(|&slot| codes.code_at(slot) != BinaryCodes::encode(&self.vector_at(slot)))
+            .collect();
+        for slot in stale {
+            codes.replace(slot, &self.vector_at(slot))?;
+        }
+        for start in (have..self.len()).step_by(4096) {
+            let end = (start + 4096).min(self.len());
+            let vectors: Vec<Vec<f32>> = (start..end).map(|slot| self.vector_at(slot)).collect();
+            let records: Vec<(u64, &[f32])> = (start..end)
+                .zip(&vectors)
+                .map(|(slot, v)| (self.ids[slot], v.as_slice()))
+                .collect();
+            codes.append(&records)?;
+        }
+
+        self.binary = Some(codes);
+        self.rescore = rescore.max(1);
+        Ok(self)
+    }
 
-            // skip vec quickly by reading raw bytes
-            let mut skip = vec![0u8; (dim * 4) as usize];
-            r.read_exact(&mut skip)?;
+    pub fn dim(&self) -> usize {
+        self.file.dim()
+    }
+
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn metric(&self) -> Metric {
+        self.file.metric()
+    }
+
+    pub fn template(&self) -> u32 {
+        self.file.template()
+    }
+
+    pub fn set_template(&mut self, template: u32) -> io::Result<()> {
+        self.file.set_template(template)
+    }
+
+    pub fn append(&mut self, vec: &[f32]) -> io::Result<u64> {
+        let id = self.file.append(vec)?;
+
+        let mut payload = Vec::with_capacity(self.file.header.payload_bytes());
+        self.file.header.encode(vec, &mut payload)?;
+        // row ใน memory ต้องตรงกับที่ decode จากไฟล์ได้
+        let mut decoded = vec![0f32; self.dim()];
+        self.file.header.decode(&payload, &mut decoded);
+        self.push_row(id, &decoded, &payload);
+        self.dead.push(false);
+
+        // code จากค่าที่เก็บจริง (ตรงกับที่ with_binary_prefilter เช็คจาก row)
+        if let Some(codes) = &mut self.binary {
+            codes.append(&[(id, &decoded)])?;
+        }
+
+        Ok(id)
+    }
+
+    pub fn search(&self, query: &[f32], top_k: usize) -> io::Result<Vec<(u64, f32)>> {
+        self.search_with(query, top_k, &SearchParams::default())
+    }
+
+    /// เหมือน `search` แต่ใช้ filter และจำนวน candidate ที่ rescore (binary prefilter) จาก `params`
+    pub fn search_with(
+        &self,
+        query: &[f32],
+        top_k: usize,
+        params: &SearchParams,
+    ) -> io::Result<Vec<(u64, f32)>> {
+        if query.len() != self.dim() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "dimension mismatch",
+            ));
+        }
+        if top_k == 0 {
+            return Ok(vec![]);
+        }
+
+        let q = self.prepare(query);
+
+        if let Some(codes) = &self.binary {
+            let n = params.rerank.unwrap_or(self.rescore).max(top_k);
+            let mut best = TopK::new(top_k);
+            let keep = |slot: usize| !self.dead[slot] && params.allows(self.ids[slot]);
+            for slot in codes.shortlist(query, n, keep) {
+                best.push(self.ids[slot], self.score(&q, slot));
+            }
+            return Ok(best.into_sorted_vec());
+        }
+
+        let best = self.scan(std::slice::from_ref(&q), top_k, params);
+        Ok(best
+            .into_iter()
+            .next()
+            .map(TopK::into_sorted_vec)
+            .unwrap_or_default())
+    }
+
+    /// ค้นหลาย query พร้อมกัน: scan record ทุกตัวรอบเดียวแล้ว score กับทุก query
+    pub fn search_batch(
+        &self,
+        queries: &[Vec<f32>],
+        top_k: usize,
+        params: &SearchParams,
+    ) -> io::Result<Vec<Vec<(u64, f32)>>> {
+        if queries.iter().any(|q| q.len() != self.dim()) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "dimension mismatch",
+            ));
+        }
+        if top_k == 0 {
+            return Ok(vec![vec![]; queries.len()]);
+        }
+
+        // binary prefilter เลือก candidate ต่อ query อยู่แล้ว -> ค้นทีละ query
+        if self.binary.is_some() {
+            return queries
+                .iter()
+                .map(|q| self.search_with(q, top_k, params))
+                .collect();
+        }
+
+        let prepared: Vec<Query> = queries.iter().map(|q| self.prepare(q)).collect();
+        Ok(self
+            .scan(&prepared, top_k, params)
+            .into_iter()
+            .map(TopK::into_sorted_vec)
+            .collect())
+    }
+
+    /// ลบ record (เขียน tombstone) คืน false ถ้าไม่มี id นี้หรือถูกลบไปแล้ว
+    pub fn delete(&mut self, id: u64) -> io::Result<bool> {
+        let slot = match self.slot_of(id) {
+            Some(slot) => slot,
+            None => return Ok(false),
+        };
+        self.file.mark_deleted(id)?;
+        self.dead[slot] = true;
+        Ok(true)
+    }
+
+    /// แทน vector ของ `id` (id เดิม) คืน false ถ้าไม่มี id นี้หรือถูกลบไปแล้ว
+    pub fn replace(&mut self, id: u64, vec: &[f32]) -> io::Result<bool> {
+        let slot = match self.slot_of(id) {
+            Some(slot) => slot,
+            None => return Ok(false),
+        };
+        self.file.write_slot(slot, id, vec)?;
+
+        let mut payload = Vec::with_capacity(self.file.header.payload_bytes());
+        self.file.header.encode(vec, &mut payload)?;
+        let mut decoded = vec![0f32; self.dim()];
+        self.file.header.decode(&payload, &mut decoded);
+        self.replace_row(slot, &decoded, &payload);
+
+        if let Some(codes) = &mut self.binary {
+            codes.replace(slot, &decoded)?;
+        }
+
+        Ok(true)
+    }
+
+    /// slot ของ record ที่ยังไม่ถูกลบ (id เรียงจากน้อยไปมากตามลำดับในไฟล์)
+    pub fn slot_of(&self, id: u64) -> Option<usize> {
+        match self.ids.binary_search(&id) {
+            Ok(slot) if !self.dead[slot] => Some(slot),
+            _ => None,
+        }
+    }
+
+    /// id ของทุก record ที่ยังไม่ถูกลบ
+    pub fn ids(&self) -> Vec<u64> {
+        self.ids
+            .iter()
+            .zip(&self.dead)
+            .filter(|(_, &dead)| !dead)
+            .map(|(&id, _)| id)
+            .collect()
+    }
+
+    pub fn is_deleted(&self, id: u64) -> bool {
+        self.file.is_deleted(id)
+    }
+
+    /// vector ที่เก็บในไฟล์ของแต่ละ id (None = ไม่มี/ถูกลบแล้ว)
+    pub fn vectors(&self, ids: &[u64]) -> io::Result<Vec<Option<Vec<f32>>>> {
+        let slots: Vec<Option<usize>> = ids.iter().map(|&id| self.slot_of(id)).collect();
+        self.file.read_vectors(&slots)
+    }
+
+    /// ตัด record ที่ถูกลบออกจากไฟล์ แล้วโหลด row (และ .bq) ใหม่ตาม slot ใหม่
+    pub fn compact(&mut self) -> io::Result<CompactReport> {
+        let report = self.file.compact()?;
+        if report.records_removed == 0 {
+            return Ok(report);
+        }
+
+        let mut fresh = Self::load(self.file.clone())?;
+        if self.binary.is_some() {
+            // slot เลื่อนหลัง compact -> with_binary_prefilter build .bq ใหม่ตั้งแต่ slot แรกที่ id ไม่ตรง
+            fresh = fresh.with_binary_prefilter(self.rescore)?;
+        }
+        *self = fresh;
+
+        Ok(report)
+    }
+
+    /// slot นี้ยังไม่ถูกลบ
+    pub fn is_live(&self, slot: usize) -> bool {
+        !self.dead[slot]
+    }
+
+    pub fn id_at(&self, slot: usize) -> u64 {
+        self.ids[slot]
+    }
+
+    /// vector ของ slot (normalize แล้วถ้า metric เป็น cosine)
+    pub fn vector_at(&self, slot: usize) -> Vec<f32> {
+        let dim = self.dim();
+        let range = slot * dim..(slot + 1) * dim;
+        let scale = self.row_scale(slot);
+        match &self.rows {
+            Rows::F32(data) => data[range].to_vec(),
+            Rows::F16 { values, .. } => values[range].iter().map(|v| v.to_f32() * scale).collect(),
+            Rows::Int8 { codes, .. } => {
+                let mut vec = vec![0f32; dim];
+                self.file.header.decode(&codes[range], &mut vec);
+                vec.iter().map(|v| v * scale).collect()
+            }
+        }
+    }
+
+    pub fn prepare(&self, query: &[f32]) -> Query {
+        let metric = self.metric();
+        let q = if metric.normalizes() {
+            normalized(query)
+        } else {
+            query.to_vec()
+        };
+        match (&self.rows, metric) {
+            // dot กับ code int8 = sum(q * min) + sum(q * scale * code)
+            (Rows::Int8 { .. }, Metric::Cosine | Metric::Dot) => {
+                let header = &self.file.header;
+                Query {
+                    base: dot(&q, &header.mins),
+                    v: q.iter().zip(&header.scales).map(|(x, s)| x * s).collect(),
+                }
+            }
+            _ => Query { v: q, base: 0.0 },
+        }
+    }
+
+    /// distance ตาม metric ของ index ระหว่าง query กับ row ที่ `slot`
+    pub fn score(&self, q: &Query, slot: usize) -> f32 {
+        match self.metric() {
+            Metric::Cosine => 1.0 - self.row_scale(slot) * self.inner(q, slot),
+            Metric::Dot => -self.inner(q, slot),
+            Metric::L2 => self.elementwise(q, slot, |d| d * d),
+            Metric::L1 => self.elementwise(q, slot, f32::abs),
+        }
+    }
+
+    /// cosine: 1/norm ของ row (f32 normalize ไว้แล้ว = 1), metric อื่นใช้ค่าเดิม
+    fn row_scale(&self, slot: usize) -> f32 {
+        match &self.rows {
+            Rows::F16 { inv_norms, .. } | Rows::Int8 { inv_norms, .. }
+                if self.metric().normalizes() =>
+            {
+                inv_norms[slot]
+            }
+            _ => 1.0,
+        }
+    }
+
+    /// sum f(q[i] - row[i]) ของ row ที่ `slot` (L2/L1)
+    fn elementwise(&self, q: &Query, slot: usize, f: impl Fn(f32) -> f32) -> f32 {
+        let dim = self.dim();
+        let range = slot * dim..(slot + 1) * dim;
+        match &self.rows {
+            Rows::F32(data) => q.v.iter().zip(&data[range]).map(|(x, y)| f(x - y)).sum(),
+            Rows::F16 { values, .. } => {
+                q.v.iter()
+                    .zip(&values[range])
+                    .map(|(x, y)| f(x - y.to_f32()))
+                    .sum()
+            }
+            Rows::Int8 { codes, .. } => {
+                let header = &self.file.header;
+                q.v.iter()
+                    .zip(&codes[range])
+                    .zip(header.mins.iter().zip(&header.scales))
+                    .map(|((x, &c), (lo, step))| f(x - (lo + c as f32 * step)))
+                    .sum()
+            }
+        }
+    }
+
+    /// dot ระหว่าง query กับค่าที่เก็บใน row ที่ `slot` (ยังไม่คูณ 1/norm)
+    fn inner(&self, q: &Query, slot: usize) -> f32 {
+        let dim = self.dim();
+        let range = slot * dim..(slot + 1) * dim;
+        match &self.rows {
+            Rows::F32(data) => dot(&q.v, &data[range]),
+            Rows::F16 { values, .. } => {
+                let row = &values[range];
+                let mut acc = [0f32; 8];
+                let mut cq = q.v.chunks_exact(8);
+                let mut cr = row.chunks_exact(8);
+                for (x, y) in (&mut cq).zip(&mut cr) {
+                    for i in 0..8 {
+                        acc[i] += x[i] * y[i].to_f32();
+                    }
+                }
+                let tail: f32 = cq
+                    .remainder()
+                    .iter()
+                    .zip(cr.remainder())
+                    .map(|(x, y)| x * y.to_f32())
+                    .sum();
+                acc.iter().sum::<f32>() + tail
+            }
+            Rows::Int8 { codes, .. } => {
+                let row = &codes[range];
+                let mut acc = [0f32; 8];
+                let mut cq = q.v.chunks_exact(8);
+                let mut cr = row.chunks_exact(8);
+                for (x, y) in (&mut cq).zip(&mut cr) {
+                    for i in 0..8 {
+                        acc[i] += x[i] * y[i] as f32;
+                    }
+                }
+                let tail: f32 = cq
+                    .remainder()
+                    .iter()
+                    .zip(cr.remainder())
+                    .map(|(x, &y)| x * y as f32)
+                    .sum();
+                q.base + acc.iter().sum::<f32>() + tail
+            }
+        }
+    }
+
+    /// ทุก record ที่ distance <= `max_distance` เรียงจากใกล้ไปไกล (ตัดที่ `cap` ถ้ามี)
+    ///
+    /// scan ทุก slot เสมอ (ไม่ใช้ binary prefilter เพราะ shortlist อาจตกบาง record ในรัศมี)
+    pub fn search_range(
+        &self,
+        query: &[f32],
+        max_distance: f32,
+        cap: Option<usize>,
+        params: &SearchParams,
+    ) -> io::Result<Vec<(u64, f32)>> {
+        if query.len() != self.dim() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "dimension mismatch",
+            ));
+        }
+
+        let q = self.prepare(query);
+        let scan_range = |start: usize, end: usize| {
+            let mut hits = Vec::new();
+            for slot in start..end {
+                let id = self.ids[slot];
+                if self.dead[slot] || !params.allows(id) {
+                    continue;
+                }
+                let d = self.score(&q, slot);
+                if d <= max_distance {
+                    hits.push((id, d));
+                }
+            }
+            hits
+        };
+
+        let mut hits = if self.len() <= SCAN_CHUNK {
+            scan_range(0, self.len())
+        } else {
+            (0..self.len().div_ceil(SCAN_CHUNK))
+                .into_par_iter()
+                .flat_map_iter(|chunk| {
+                    let start = chunk * SCAN_CHUNK;
+                    scan_range(start, (start + SCAN_CHUNK).min(self.len()))
+                })
+                .collect()
+        };
+        sort_hits(&mut hits);
+        if let Some(cap) = cap {
+            hits.truncate(cap);
+        }
+        Ok(hits)
+    }
+
+    /// top-k แบบ exact เฉพาะ id ใน `allow` (ใช้เมื่อ index แบบ approximate
+    /// หาผลที่ผ่าน filter ได้ไม่ครบ)
+    pub fn search_ids(&self, q: &Query, top_k: usize, allow: &HashSet<u64>) -> Vec<(u64, f32)> {
+        let mut best = TopK::new(top_k);
+        for &id in allow {
+            if let Some(slot) = self.slot_of(id) {
+                best.push(id, self.score(q, slot));
+            }
+        }
+        best.into_sorted_vec()
+    }
+
+    /// score ทุก record (ที่ผ่าน filter) กับทุก query แล้วคืน top-k ต่อ query
+    fn scan(&self, queries: &[Query], top_k: usize, params: &SearchParams) -> Vec<TopK> {
+        let scan_range = |start: usize, end: usize| {
+            let mut best: Vec<TopK> = queries.iter().map(|_| TopK::new(top_k)).collect();
+            for slot in start..end {
+                let id = self.ids[slot];
+                if self.dead[slot] || !params.allows(id) {
+                    continue;
+                }
+                for (q, b) in queries.iter().zip(&mut best) {
+                    b.push(id, self.score(q, slot));
+                }
+            }
+            best
+        };
+
+        if self.len() <= SCAN_CHUNK {
+            return scan_range(0, self.len());
+        }
+
+        // แบ่ง slot เป็นช่วงๆ ให้แต่ละ thread หา top-k ของตัวเอง แล้วค่อยรวม
+        (0..self.len().div_ceil(SCAN_CHUNK))
+            .into_par_iter()
+            .map(|chunk| {
+                let start = chunk * SCAN_CHUNK;
+                scan_range(start, (start + SCAN_CHUNK).min(self.len()))
+            })
+            .reduce(
+                || queries.iter().map(|_| TopK::new(top_k)).collect(),
+                |mut a, b| {
+                    for (x, y) in a.iter_mut().zip(b) {
+                        x.merge(y);
+                    }
+                    a
+                },
+            )
+    }
+
+    fn replace_row(&mut self, slot: usize, decoded: &[f32], payload: &[u8]) {
+        let dim = self.dim();
+        let range = slot * dim..(slot + 1) * dim;
+        match &mut self.rows {
+            Rows::F32(data) if self.file.header.normalized => {
+                data[range].copy_from_slice(&normalized(decoded))
+            }
+            Rows::F32(data) => data[range].copy_from_slice(decoded),
+            Rows::F16 { values, inv_norms } => {
+                for (dst, &v) in values[range].iter_mut().zip(decoded) {
+                    *dst = f16::from_f32(v);
+                }
+                inv_norms[slot] = inv_norm(decoded);
+            }
+            Rows::Int8 { codes, inv_norms } => {
+                codes[range].copy_from_slice(payload);
+                inv_norms[slot] = inv_norm(decoded);
+            }
+        }
+    }
+
+    fn push_row(&mut self, id: u64, decoded: &[f32], payload: &[u8]) {
+        self.ids.push(id);
+        match &mut self.rows {
+            Rows::F32(data) if self.file.header.normalized => data.extend(normalized(decoded)),
+            Rows::F32(data) => data.extend_from_slice(decoded),
+            Rows::F16 { values, inv_norms } => {
+                values.extend(decoded.iter().map(|&v| f16::from_f32(v)));
+                inv_norms.push(inv_norm(decoded));
+            }
+            Rows::Int8 { codes, inv_norms } => {
+                codes.extend_from_slice(payload);
+                inv_norms.push(inv_norm(decoded));
+            }
+        }
+    }
+}
+
+fn inv_norm(vec: &[f32]) -> f32 {
+    let n = norm(vec);
+    if n == 0.0 {
+        0.0
+    } else {
+        1.0 / n
+    }
+}
+
+fn corrupt_record(slot: usize) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!(
+            "checksum mismatch in index record at slot {} (run `backend verify-index --repair`)",
+            slot
+        ),
+    )
+}
+
+fn remove_if_exists(path: &str) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+fn file_len(path: &str) -> io::Result<u64> {
+    match fs::metadata(path) {
+        Ok(m) => Ok(m.len()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(0),
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    const DIM: usize = 4;
+    const RECORDS: usize = 5;
+
+    fn vector(i: u64) -> Vec<f32> {
+        let x = i as f32;
+        vec![x + 1.0, 1.0 - x, (i % 3) as f32, 0.5 * x]
+    }
+
+    fn spec(precision: Precision) -> VectorSpec {
+        VectorSpec {
+            dim: DIM,
+            precision,
+            metric: Metric::Cosine,
+            model: "test-model".to_string(),
+            template: 0,
+        }
+    }
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("rvix-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// kill ระหว่าง append ได้ทุก byte: ไฟล์จะเหลือ prefix ของไฟล์ที่เขียนเสร็จ
+    /// ส่วน meta อาจ bump แล้ว, ยังไม่ bump, ว่าง หรือหายไป
+    #[test]
+    fn recovers_from_kill_at_every_byte_offset() {
+        for precision in [Precision::F32, Precision::F16, Precision::Int8] {
+            let dir = scratch_dir(&format!("kill-{:?}", precision));
+            let full_path = dir.join("full.index");
+            let full_path = full_path.to_str().unwrap();
+
+            // int8 calibrate ได้จาก vector ที่มีแล้วเท่านั้น -> เขียนเป็น f32 แล้ว convert
+            let mut full = FlatIndex::open_or_create(full_path, &spec(Precision::F32)).unwrap();
+            for i in 0..RECORDS as u64 {
+                full.append(&vector(i)).unwrap();
+            }
+            drop(full);
+            let full = FlatIndex::open_or_create(full_path, &spec(precision)).unwrap();
+            let bytes = fs::read(full_path).unwrap();
+            let header_len = full.file.header.len() as usize;
+            let record_bytes = full.file.header.record_bytes() as usize;
+            assert_eq!(bytes.len(), header_len + RECORDS * record_bytes);
+
+            let path = dir.join("crash.index");
+            let path = path.to_str().unwrap();
+            let meta_path = format!("{}.meta", path);
+
+            for cut in header_len..=bytes.len() {
+                let complete = (cut - header_len) / record_bytes;
+                let torn = !(cut - header_len).is_multiple_of(record_bytes);
+
+                let mut metas: Vec<Option<Vec<u8>>> = vec![
+                    Some((complete as u64 + 1).to_le_bytes().to_vec()),
+                    Some(vec![]),
+                    Some(vec![7, 0, 0]),
+                    None,
+                ];
+                if !torn && complete > 0 {
+                    // record เขียนครบแล้วแต่ยังไม่ได้ bump next_id
+                    metas.push(Some((complete as u64).to_le_bytes().to_vec()));
+                }
+
+                for meta in metas {
+                    fs::write(path, &bytes[..cut]).unwrap();
+                    match &meta {
+                        Some(m) => fs::write(&meta_path, m).unwrap(),
+                        None => {
+                            let _ = fs::remove_file(&meta_path);
+                        }
+                    }
+                    let case = format!("{:?} cut at {} meta {:?}", precision, cut, meta);
+
+                    let mut index = FlatIndex::open_or_create(path, &spec(precision)).unwrap();
+                    assert_eq!(index.len(), complete, "{}", case);
+                    assert_eq!(
+                        fs::metadata(path).unwrap().len() as usize,
+                        header_len + complete * record_bytes,
+                        "{}",
+                        case
+                    );
+                    let hits = index.search(&vector(0), RECORDS).unwrap();
+                    assert_eq!(hits.len(), complete, "{}", case);
+
+                    let id = index.append(&vector(99)).unwrap();
+                    assert_eq!(id, complete as u64 + 1, "{}", case);
+                    drop(index);
+
+                    let index = FlatIndex::open_or_create(path, &spec(precision)).unwrap();
+                    assert_eq!(index.len(), complete + 1, "{}", case);
+                    assert!(index.ids.windows(2).all(|w| w[0] < w[1]), "{}", case);
+                    assert_eq!(index.ids.last(), Some(&(complete as u64 + 1)), "{}", case);
+                }
+            }
+
+            fs::remove_dir_all(&dir).unwrap();
+        }
+    }
+
+    /// compact ตอนปิด binary ทำให้ slot เลื่อน: เปิด binary ใหม่ต้อง build .bq ใหม่
+    /// ตั้งแต่ slot แรกที่ id ไม่ตรง ไม่ใช่ใช้ code เดิมที่ชี้ไปผิด record
+    #[test]
+    fn binary_codes_follow_slots_after_compact_without_binary() {
+        let dir = scratch_dir("bq-compact");
+        let path = dir.join("bq.index");
+        let path = path.to_str().unwrap();
+        let spec = VectorSpec {
+            dim: 8,
+            ..spec(Precision::F32)
+        };
+        // sign-bit ของแต่ละ record ไม่ซ้ำกัน -> rescore 1 ตัวก็ได้ผลเดียวกับ exact search
+        let vector = |i: u64| -> Vec<f32> {
+            (0..8)
+                .map(|bit| if (i >> bit) & 1 == 1 { 1.0 } else { -1.0 })
+                .collect()
+        };
+
+        let mut index = FlatIndex::open_or_create(path, &spec)
+            .unwrap()
+            .with_binary_prefilter(1)
+            .unwrap();
+        for i in 1..=12 {
+            index.append(&vector(i)).unwrap();
+        }
+        drop(index);
+
+        let mut index = FlatIndex::open_or_create(path, &spec).unwrap();
+        index.delete(2).unwrap();
+        index.delete(5).unwrap();
+        index.compact().unwrap();
+        for i in 13..=14 {
+            index.append(&vector(i)).unwrap();
+        }
+        drop(index);
+
+        let exact = FlatIndex::open_or_create(path, &spec).unwrap();
+        let index = FlatIndex::open_or_create(path, &spec)
+            .unwrap()
+            .with_binary_prefilter(1)
+            .unwrap();
+        assert_eq!(index.binary.as_ref().unwrap().ids(), &exact.ids[..]);
+        for id in exact.ids() {
+            assert_eq!(
+                index.search(&vector(id), 1).unwrap(),
+                exact.search(&vector(id), 1).unwrap(),
+                "id {}",
+                id
+            );
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// replace ตอนปิด binary: id ใน .bq ยังตรง แต่ code ต้องถูกเขียนใหม่ตาม vector ใหม่
+    #[test]
+    fn binary_codes_follow_replace_without_binary() {
+        let dir = scratch_dir("bq-replace");
+        let path = dir.join("bq.index");
+        let path = path.to_str().unwrap();
+        let spec = VectorSpec {
+            dim: 8,
+            ..spec(Precision::F32)
+        };
+        let vector = |i: u64| -> Vec<f32> {
+            (0..8)
+                .map(|bit| if (i >> bit) & 1 == 1 { 1.0 } else { -1.0 })
+                .collect()
+        };
+
+        let mut index = FlatIndex::open_or_create(path, &spec)
+            .unwrap()
+            .with_binary_prefilter(1)
+            .unwrap();
+        for i in 1..=12 {
+            index.append(&vector(i)).unwrap();
+        }
+        drop(index);
 
-            count += 1;
+        // id 3 ได้ sign-bit ใหม่ที่ไม่ซ้ำกับ record อื่น
+        let mut index = FlatIndex::open_or_create(path, &spec).unwrap();
+        assert!(index.replace(3, &vector(200)).unwrap());
+        drop(index);
 
-            // (optional) sanity: if seekable, could use file size math, but keep simple
-            let _ = record_bytes;
+        let exact = FlatIndex::open_or_create(path, &spec).unwrap();
+        let index = FlatIndex::open_or_create(path, &spec)
+            .unwrap()
+            .with_binary_prefilter(1)
+            .unwrap();
+        let hits = index.search(&vector(200), 1).unwrap();
+        assert_eq!(hits[0].0, 3);
+        for id in exact.ids() {
+            assert_eq!(
+                index.search(&vector(id), 1).unwrap(),
+                exact.search(&vector(id), 1).unwrap(),
+                "id {}",
+                id
+            );
         }
 
-        Ok(count + 1) // since id is sequential, next_id == record_count
+        fs::remove_dir_all(&dir).unwrap();
     }
 }
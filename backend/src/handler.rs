@@ -1,193 +1,517 @@
-use std::collections::{HashMap, HashSet};
-use std::fs;
-use std::path::Path;
+use std::collections::HashMap;
+use std::io;
 use std::sync::Arc;
 
-use axum::{extract::State, response::IntoResponse, Json};
+use axum::{
+    extract::{Path as UrlPath, Query, State},
+    response::{IntoResponse, Response},
+    Json,
+};
 use indexmap::IndexMap;
-use serde_json::{to_value, Value};
-
-use crate::model::SearchRequest;
-use crate::presenter::{res_error, res_error_msg, res_success};
-use crate::utils::load_model_fields;
+use serde_json::{to_value, Map, Value};
+
+use crate::collection::{Collection, CollectionParam};
+use crate::distance::Metric;
+use crate::doc_store;
+use crate::filter;
+use crate::model::{
+    CreateCollectionRequest, DeleteRequest, DocumentPath, GetDocumentQuery, ListRequest,
+    MultiGetRequest, SearchBatchRequest, SearchRequest, SortOrder,
+};
+use crate::paging::{self, ListCursor, Page};
+use crate::presenter::{
+    res_error, res_error_msg, res_field_errors, res_list, res_page, res_success,
+};
+use crate::vector_index::SearchParams;
 use crate::AppState;
 
-// fn build_embedding_text(payload: &Value) -> String {
-//     let product_id = payload
-//         .get("product_id")
-//         .and_then(|v| v.as_str())
-//         .unwrap_or("");
-//     let title = payload
-//         .get("review_title")
-//         .and_then(|v| v.as_str())
-//         .unwrap_or("");
-//     let body = payload
-//         .get("review_body")
-//         .and_then(|v| v.as_str())
-//         .unwrap_or("");
-//     let rating = payload
-//         .get("review_rating")
-//         .map(|v| v.to_string())
-//         .unwrap_or_else(|| "".into());
-//
-//     format!(
-//         "product_id: {}\nreview_title: {}\nreview_body: {}\nreview_rating: {}",
-//         product_id, title, body, rating
-//     )
-// }
-
-fn build_embedding_text(payload: &Value) -> String {
-    payload
-        .get("review_body")
-        .and_then(|v| v.as_str())
-        .unwrap_or("")
-        .to_string()
-}
+/// metadata ของ document ตามลำดับ field ใน schema (embedding = null)
+fn ordered_document(id: u64, item: &Value, model_fields: &[String]) -> IndexMap<String, Value> {
+    let mut ordered = IndexMap::new();
+    ordered.insert("id".to_string(), Value::Number(id.into()));
 
-fn parse_u64(v: &Value) -> Option<u64> {
-    match v {
-        Value::Number(n) => n.as_u64(),
-        Value::String(s) => s.parse::<u64>().ok(),
-        _ => None,
+    for field in model_fields {
+        if field == "embedding" {
+            ordered.insert(field.clone(), Value::Null);
+            continue;
+        }
+        let value = item.get(field).cloned().unwrap_or(Value::Null);
+        ordered.insert(field.clone(), value);
     }
+    ordered
 }
 
-pub async fn get_data(
-    State(state): State<Arc<AppState>>,
-    Json(payload): Json<SearchRequest>,
-) -> impl IntoResponse {
-    let json_path = "src/data/reviews.jsonl";
-    let index_path = "src/data/reviews.index";
+/// map (id, distance) -> metadata ตามลำดับ field ใน schema + distance + score
+fn present_hits(
+    hits: Vec<(u64, f32)>,
+    metric: Metric,
+    by_id: &HashMap<u64, Value>,
+    model_fields: &[String],
+) -> Vec<Value> {
+    hits.into_iter()
+        .filter_map(|(id, distance)| {
+            let item = by_id.get(&id)?;
 
-    if !Path::new(json_path).exists() {
-        return res_success(Vec::<Value>::new());
-    }
+            let mut ordered = ordered_document(id, item, model_fields);
+            ordered.insert("distance".to_string(), float_value(distance));
+            ordered.insert("score".to_string(), float_value(metric.score(distance)));
 
-    // โหลด JSONL เป็น Vec + map id -> item
-    let content = match fs::read_to_string(json_path) {
-        Ok(c) => c,
-        Err(_) => return res_success(Vec::<Value>::new()),
-    };
+            Some(to_value(ordered).unwrap())
+        })
+        .collect()
+}
 
-    let mut items: Vec<Value> = Vec::new();
-    let mut by_id: HashMap<u64, Value> = HashMap::new();
+fn float_value(v: f32) -> Value {
+    serde_json::Number::from_f64(v as f64)
+        .map(Value::Number)
+        .unwrap_or(Value::Null)
+}
 
-    for line in content.lines() {
-        let line = line.trim();
-        if line.is_empty() {
-            continue;
-        }
-        if let Ok(v) = serde_json::from_str::<Value>(line) {
-            if let Some(id) = v.get("id").and_then(parse_u64) {
-                by_id.insert(id, v.clone());
-            }
-            items.push(v);
+/// document ของ hit จาก store (อ่านเฉพาะบรรทัดของ id ที่ต้องใช้)
+async fn hit_documents(coll: &Collection, hits: &[(u64, f32)]) -> io::Result<HashMap<u64, Value>> {
+    let ids: Vec<u64> = hits.iter().map(|&(id, _)| id).collect();
+    let docs = coll.documents.read().await.get_many(&ids)?;
+    Ok(ids
+        .into_iter()
+        .zip(docs)
+        .filter_map(|(id, doc)| Some((id, doc?)))
+        .collect())
+}
+
+pub async fn get_data(
+    State(state): State<Arc<AppState>>,
+    CollectionParam(coll): CollectionParam,
+    Json(payload): Json<SearchRequest>,
+) -> impl IntoResponse {
+    let model_fields = coll.schema.names();
+    for filter in &payload.filters {
+        if let Err(msg) = filter.validate(&model_fields) {
+            return res_error_msg(msg);
         }
     }
+    if let Err(msg) = state.search.check_tuning(payload.nprobe, payload.rerank) {
+        return res_error_msg(msg);
+    }
+    if payload.max_distance.is_some() && payload.min_similarity.is_some() {
+        return res_error_msg("use either max_distance or min_similarity, not both");
+    }
+    if payload
+        .max_distance
+        .or(payload.min_similarity)
+        .is_some_and(f32::is_nan)
+    {
+        return res_error_msg("max_distance/min_similarity must be a number");
+    }
 
-    let model_fields = load_model_fields();
+    // ไม่มี query = ไม่มี distance ให้คืน -> ใช้ /list-data แทน
     let query = payload.query.trim();
-    let top_k: usize = 10;
-    // let top_k = payload.top_k.unwrap_or(10) as usize;
-
-    // ถ้า query ว่าง -> คืน metadata อย่างเดียว (ยังเคารพ top_k)
     if query.is_empty() {
-        let mapped: Vec<Value> = items
-            .into_iter()
-            .take(top_k)
-            .map(|item| {
-                let mut ordered = IndexMap::new();
-
-                if let Some(idv) = item.get("id").cloned() {
-                    ordered.insert("id".to_string(), idv);
-                }
-
-                for field in &model_fields {
-                    if field == "embedding" {
-                        ordered.insert(field.clone(), Value::Null);
-                        continue;
-                    }
-                    let value = item.get(field).cloned().unwrap_or(Value::Null);
-                    ordered.insert(field.clone(), value);
-                }
-
-                to_value(ordered).unwrap()
-            })
-            .collect();
-
-        return res_success(mapped);
+        return res_error_msg("query must not be empty (use /list-data or /collections/{name}/list to browse documents)");
     }
+    let radius_search = payload.max_distance.is_some() || payload.min_similarity.is_some();
+    let max_results = match state.search.radius_cap(payload.max_results) {
+        Ok(n) => n,
+        Err(msg) => return res_error_msg(msg),
+    };
+
+    // radius search ได้ hit ไม่เกิน max_results แบ่งหน้าละ max_top_k ถ้าไม่ส่ง top_k มา
+    // top-k search default 10
+    // cursor ผูกกับ query + filter + รัศมี ใช้ข้าม request ที่ต่างกันไม่ได้
+    let fingerprint = paging::fingerprint(&format!(
+        "{:?}",
+        (
+            query,
+            &payload.filters,
+            payload.max_distance,
+            payload.min_similarity,
+            payload.max_results
+        )
+    ));
+    let page = match Page::resolve(
+        payload.top_k,
+        payload.offset,
+        payload.cursor.as_deref(),
+        Some(if radius_search {
+            state.search.max_top_k
+        } else {
+            10
+        }),
+        fingerprint,
+        &state.search,
+    ) {
+        Ok(page) => page,
+        Err(msg) => return res_error_msg(msg),
+    };
 
-    if !Path::new(index_path).exists() {
-        return res_success(Vec::<Value>::new());
+    if coll.documents.read().await.is_empty() {
+        return res_page(Vec::<Value>::new(), 0, None);
     }
 
+    // filter -> ชุด id ที่ผ่าน ให้ index เช็คระหว่าง scan (ไม่ใช่ตัดหลังได้ top-k)
+    let (allow, total_documents) = {
+        let documents = coll.documents.read().await;
+        let allow = if payload.filters.is_empty() {
+            None
+        } else {
+            match filter::allowed_ids(&payload.filters, documents.as_ref()) {
+                Ok(ids) => Some(Arc::new(ids)),
+                Err(e) => return res_error_msg(format!("read documents error: {}", e)),
+            }
+        };
+        (allow, documents.len())
+    };
+
     //  embed query
     let qvecs = {
-        let mut embedder = state.embedder.lock().await;
+        let mut embedder = coll.embedder.lock().await;
         match embedder.embed(vec![query.to_string()], None) {
             Ok(v) => v,
             Err(e) => return res_error_msg(format!("embedding error: {}", e)),
         }
     };
-    let qvec = match qvecs.get(0) {
+    let qvec = match qvecs.first() {
         Some(v) => v.as_slice(),
         None => return res_error_msg("embedding error: empty query vector"),
     };
 
-    //  search จาก FlatIndex
-    let hits = {
-        let index = state.index.lock().await;
+    // top-k: หา offset + top_k ตัวแรกแล้วค่อยตัดเป็นหน้า
+    let window = page.window().unwrap_or(10);
+
+    //  search จาก vector index (scan ใน blocking pool ไม่ให้ block handler อื่น)
+    let (hits, metric, allowed) = {
+        let index = coll.index.clone().read_owned().await;
         if index.dim() != qvec.len() {
             return res_error_msg("index dim mismatch with query embedding dim");
         }
-        match index.search(qvec, top_k) {
-            Ok(v) => v,
+        let metric = index.metric();
+        // radius search: แปลง min_similarity เป็นรัศมีตาม metric ของ index
+        let radius = payload
+            .max_distance
+            .or(payload.min_similarity.map(|s| metric.distance_for_score(s)));
+        let allowed = allow.as_ref().map(|ids| ids.len());
+        let params = SearchParams {
+            nprobe: payload.nprobe,
+            rerank: payload.rerank,
+            allow,
+        };
+        // radius: ต้องได้ทุก hit ในรัศมี (ไม่เกิน max_results) เพื่อนับ total_considered
+        let qvec = qvec.to_vec();
+        let result = tokio::task::spawn_blocking(move || match radius {
+            Some(max_distance) => {
+                index.search_range(&qvec, max_distance, Some(max_results), &params)
+            }
+            None => index.search_with(&qvec, window, &params),
+        })
+        .await;
+        match result {
+            Ok(Ok(v)) => (v, metric, allowed),
+            Ok(Err(e)) => return res_error_msg(format!("index search error: {}", e)),
             Err(e) => return res_error_msg(format!("index search error: {}", e)),
         }
     };
 
-    //  map id -> metadata + attach distance
-    let results: Vec<Value> = hits
+    // radius: จำนวน hit ในรัศมี (หลัง max_results), top-k: จำนวน document ที่ผ่าน filter
+    let total_considered = if radius_search {
+        hits.len()
+    } else {
+        allowed.unwrap_or(total_documents)
+    };
+    // index คืนไม่ครบ window (เช่น ANN หาได้ไม่พอ) = ไม่มีหน้าถัดไปแล้ว
+    let exhausted = !radius_search && hits.len() < window;
+    let next_cursor = (!exhausted)
+        .then(|| page.next_cursor(total_considered, fingerprint))
+        .flatten();
+
+    //  อ่าน metadata เฉพาะ hit ในหน้านี้ แล้วแนบ distance/score
+    let hits = page.apply(hits);
+    let by_id = match hit_documents(&coll, &hits).await {
+        Ok(docs) => docs,
+        Err(e) => return res_error_msg(format!("read documents error: {}", e)),
+    };
+    res_page(
+        present_hits(hits, metric, &by_id, &model_fields),
+        total_considered,
+        next_cursor,
+    )
+}
+
+pub async fn list_data(
+    State(state): State<Arc<AppState>>,
+    CollectionParam(coll): CollectionParam,
+    Json(payload): Json<ListRequest>,
+) -> impl IntoResponse {
+    let model_fields = coll.schema.names();
+    for filter in &payload.filters {
+        if let Err(msg) = filter.validate(&model_fields) {
+            return res_error_msg(msg);
+        }
+    }
+    let sort_by = payload.sort_by.as_deref().unwrap_or("id");
+    if sort_by != "id" && (sort_by == "embedding" || !model_fields.iter().any(|f| f == sort_by)) {
+        return res_error_msg(format!("unknown sort field: {}", sort_by));
+    }
+    let limit = payload.limit.unwrap_or(10);
+    if limit == 0 || limit > state.search.max_top_k {
+        return res_error_msg(format!(
+            "limit must be between 1 and {}",
+            state.search.max_top_k
+        ));
+    }
+
+    // cursor ผูกกับ sort + filter ใช้ข้าม request ที่ต่างกันไม่ได้
+    let fingerprint =
+        paging::fingerprint(&format!("{:?}", (sort_by, payload.order, &payload.filters)));
+    let after = match payload.cursor.as_deref() {
+        Some(cursor) => match ListCursor::decode(cursor, fingerprint) {
+            Ok(c) => Some(c),
+            Err(msg) => return res_error_msg(msg),
+        },
+        None => None,
+    };
+
+    //  document ที่ผ่าน filter เรียงด้วย (ค่าของ field, id)
+    let sort_value = |id: u64, doc: &Value| -> Value {
+        if sort_by == "id" {
+            Value::Number(id.into())
+        } else {
+            doc.get(sort_by).cloned().unwrap_or(Value::Null)
+        }
+    };
+    let order = payload.order;
+    let compare = |a: &(Value, u64), b: &(Value, u64)| {
+        let ord = paging::compare_values(&a.0, &b.0).then(a.1.cmp(&b.1));
+        match order {
+            SortOrder::Asc => ord,
+            SortOrder::Desc => ord.reverse(),
+        }
+    };
+    let documents = coll.documents.read().await;
+    let mut keys: Vec<(Value, u64)> = Vec::new();
+    let scanned = documents.scan(&mut |id, doc| {
+        if payload.filters.iter().all(|f| f.matches(doc)) {
+            keys.push((sort_value(id, doc), id));
+        }
+    });
+    if let Err(e) = scanned {
+        return res_error_msg(format!("read documents error: {}", e));
+    }
+    keys.sort_by(compare);
+    let total = keys.len();
+
+    //  ข้ามถึงหลัง cursor (keyset) แล้วเอา limit ตัว
+    let start = match &after {
+        Some(c) => {
+            let key = (c.value.clone(), c.id);
+            keys.partition_point(|k| compare(k, &key).is_le())
+        }
+        None => 0,
+    };
+    let end = (start + limit).min(total);
+    let next_cursor = (end < total).then(|| {
+        let (value, id) = keys[end - 1].clone();
+        ListCursor { value, id }.encode(fingerprint)
+    });
+
+    let ids: Vec<u64> = keys[start..end].iter().map(|&(_, id)| id).collect();
+    let docs = match documents.get_many(&ids) {
+        Ok(docs) => docs,
+        Err(e) => return res_error_msg(format!("read documents error: {}", e)),
+    };
+    let items: Vec<Value> = ids
         .into_iter()
-        .filter_map(|(id, distance)| {
-            let item = by_id.get(&id)?.clone();
-
-            let mut ordered = IndexMap::new();
-            ordered.insert("id".to_string(), Value::Number(id.into()));
-
-            for field in &model_fields {
-                if field == "embedding" {
-                    ordered.insert(field.clone(), Value::Null);
-                    continue;
-                }
-                let value = item.get(field).cloned().unwrap_or(Value::Null);
-                ordered.insert(field.clone(), value);
-            }
+        .zip(docs)
+        .filter_map(|(id, doc)| Some(to_value(ordered_document(id, &doc?, &model_fields)).unwrap()))
+        .collect();
 
-            ordered.insert(
-                "distance".to_string(),
-                serde_json::Number::from_f64(distance as f64)
-                    .map(Value::Number)
-                    .unwrap_or(Value::Null),
-            );
+    res_list(items, total, next_cursor)
+}
 
+/// document ของแต่ละ id ตามลำดับที่ขอ (None = ไม่มี/ถูกลบแล้ว)
+/// `include_vector` -> ใส่ vector จาก index ของ collection ใน field embedding
+async fn fetch_documents(
+    coll: &Collection,
+    ids: &[u64],
+    include_vector: bool,
+) -> Result<Vec<Option<Value>>, String> {
+    let model_fields = coll.schema.names();
+    let docs = coll
+        .documents
+        .read()
+        .await
+        .get_many(ids)
+        .map_err(|e| format!("read documents error: {}", e))?;
+
+    let vectors = if include_vector {
+        let index = coll.index.read().await;
+        index
+            .vectors(ids)
+            .map_err(|e| format!("index read error: {}", e))?
+    } else {
+        vec![None; ids.len()]
+    };
+
+    Ok(ids
+        .iter()
+        .zip(docs)
+        .zip(vectors)
+        .map(|((&id, doc), vector)| {
+            let mut ordered = ordered_document(id, &doc?, &model_fields);
+            if include_vector {
+                let embedding = vector.map(|v| v.into_iter().map(float_value).collect());
+                ordered.insert(
+                    "embedding".to_string(),
+                    embedding.map(Value::Array).unwrap_or(Value::Null),
+                );
+            }
             Some(to_value(ordered).unwrap())
         })
+        .collect())
+}
+
+pub async fn get_document(
+    CollectionParam(coll): CollectionParam,
+    UrlPath(DocumentPath { id }): UrlPath<DocumentPath>,
+    Query(params): Query<GetDocumentQuery>,
+) -> impl IntoResponse {
+    match fetch_documents(&coll, &[id], params.include_vector).await {
+        Ok(mut docs) => match docs.pop().flatten() {
+            Some(doc) => res_success(doc),
+            None => res_error_msg(format!("id {} not found", id)),
+        },
+        Err(msg) => res_error_msg(msg),
+    }
+}
+
+pub async fn multi_get_documents(
+    State(state): State<Arc<AppState>>,
+    CollectionParam(coll): CollectionParam,
+    Json(payload): Json<MultiGetRequest>,
+) -> impl IntoResponse {
+    if payload.ids.is_empty() {
+        return res_error_msg("ids must not be empty");
+    }
+    if payload.ids.len() > state.search.max_top_k {
+        return res_error_msg(format!(
+            "at most {} ids per request",
+            state.search.max_top_k
+        ));
+    }
+
+    let docs = match fetch_documents(&coll, &payload.ids, payload.include_vector).await {
+        Ok(docs) => docs,
+        Err(msg) => return res_error_msg(msg),
+    };
+
+    //  คงลำดับตาม ids ที่ขอ, id ที่ไม่มีแยกไว้ใน missing
+    let mut documents = Vec::new();
+    let mut missing = Vec::new();
+    for (&id, doc) in payload.ids.iter().zip(docs) {
+        match doc {
+            Some(doc) => documents.push(doc),
+            None => missing.push(id),
+        }
+    }
+
+    res_success(serde_json::json!({
+        "documents": documents,
+        "missing": missing,
+    }))
+}
+
+pub async fn search_batch(
+    State(state): State<Arc<AppState>>,
+    CollectionParam(coll): CollectionParam,
+    Json(payload): Json<SearchBatchRequest>,
+) -> impl IntoResponse {
+    let queries: Vec<String> = payload
+        .queries
+        .iter()
+        .map(|q| q.trim().to_string())
+        .collect();
+    if queries.is_empty() {
+        return res_error_msg("queries must not be empty");
+    }
+    if let Some(i) = queries.iter().position(|q| q.is_empty()) {
+        return res_error_msg(format!("queries[{}] is empty", i));
+    }
+
+    let top_k = payload.top_k.unwrap_or(10);
+    if top_k == 0 || top_k > state.search.max_top_k {
+        return res_error_msg(format!(
+            "top_k must be between 1 and {}",
+            state.search.max_top_k
+        ));
+    }
+    if let Err(msg) = state.search.check_tuning(payload.nprobe, payload.rerank) {
+        return res_error_msg(msg);
+    }
+
+    if coll.documents.read().await.is_empty() {
+        let empty: Vec<Value> = queries
+            .into_iter()
+            .map(|q| serde_json::json!({ "query": q, "results": [] }))
+            .collect();
+        return res_success(empty);
+    }
+
+    //  embed ทุก query ในครั้งเดียว
+    let qvecs = {
+        let mut embedder = coll.embedder.lock().await;
+        match embedder.embed(queries.clone(), None) {
+            Ok(v) => v,
+            Err(e) => return res_error_msg(format!("embedding error: {}", e)),
+        }
+    };
+    if qvecs.len() != queries.len() {
+        return res_error_msg("embedding error: query vector count mismatch");
+    }
+
+    //  search ทุก query ใน index pass เดียว
+    let (hits, metric) = {
+        let index = coll.index.clone().read_owned().await;
+        if qvecs.iter().any(|v| v.len() != index.dim()) {
+            return res_error_msg("index dim mismatch with query embedding dim");
+        }
+        let metric = index.metric();
+        let params = SearchParams {
+            nprobe: payload.nprobe,
+            rerank: payload.rerank,
+            allow: None,
+        };
+        let result =
+            tokio::task::spawn_blocking(move || index.search_batch(&qvecs, top_k, &params)).await;
+        match result {
+            Ok(Ok(v)) => (v, metric),
+            Ok(Err(e)) => return res_error_msg(format!("index search error: {}", e)),
+            Err(e) => return res_error_msg(format!("index search error: {}", e)),
+        }
+    };
+
+    let all_hits: Vec<(u64, f32)> = hits.iter().flatten().copied().collect();
+    let by_id = match hit_documents(&coll, &all_hits).await {
+        Ok(docs) => docs,
+        Err(e) => return res_error_msg(format!("read documents error: {}", e)),
+    };
+    let model_fields = coll.schema.names();
+
+    let results: Vec<Value> = queries
+        .into_iter()
+        .zip(hits)
+        .map(|(query, hits)| {
+            serde_json::json!({
+                "query": query,
+                "results": present_hits(hits, metric, &by_id, &model_fields),
+            })
+        })
         .collect();
 
     res_success(results)
 }
 
 pub async fn create_data(
-    State(state): State<Arc<AppState>>,
+    CollectionParam(coll): CollectionParam,
     json: Result<Json<Value>, axum::extract::rejection::JsonRejection>,
 ) -> impl IntoResponse {
-    use std::fs::OpenOptions;
-    use std::io::Write;
-
-    let model_fields = load_model_fields();
-
     let payload = match json {
         Ok(Json(value)) => value,
         Err(err) => return res_error(err),
@@ -199,97 +523,368 @@ pub async fn create_data(
         None => return res_error_msg("payload must be a JSON object"),
     };
 
-    let server_fields: HashSet<String> = ["embedding".to_string(), "id".to_string()]
-        .into_iter()
-        .collect();
-
-    for f in server_fields.iter() {
+    for f in ["embedding", "id"] {
         if obj.contains_key(f) {
             return res_error_msg(format!("do not provide '{}' (server will generate it)", f));
         }
     }
 
-    let allowed_fields: HashSet<String> = model_fields
-        .iter()
-        .cloned()
-        .filter(|f| !server_fields.contains(f))
-        .collect();
+    //  type / constraint ตาม schema ของ collection (error ครบทุก field ในครั้งเดียว)
+    let fields = match coll.schema.validate(obj) {
+        Ok(fields) => fields,
+        Err(errors) => return res_field_errors(errors),
+    };
 
-    let payload_fields: HashSet<String> = obj.keys().cloned().collect();
+    //  สร้าง payload สำหรับ JSONL: id + field ตาม schema (ไม่เก็บ embedding แล้ว)
+    //  id ได้จาก index ตอน append -> จองตำแหน่งแรกไว้ก่อน
+    let mut ordered = IndexMap::new();
+    ordered.insert("id".to_string(), Value::Null);
+    ordered.extend(fields);
 
-    // required เฉพาะ field ที่ client ต้องส่ง
-    for field in allowed_fields.iter() {
-        if payload.get(field).is_none() {
-            return res_error_msg(format!("{} is required", field));
+    //  สร้าง embedding ตาม embedding template (field ที่ไม่ได้ส่งมา = ค่า default)
+    let text = coll
+        .template
+        .render(&to_value(&ordered).unwrap_or_default());
+    let emb = {
+        let mut embedder = coll.embedder.lock().await;
+        match embedder.embed(vec![text], None) {
+            Ok(v) => v,
+            Err(e) => return res_error_msg(format!("embedding error: {}", e)),
         }
+    };
+    let embedding_vec = emb.first().cloned().unwrap_or_default();
+
+    //  2 phase: append vector -> เขียน metadata, เขียน metadata ไม่ได้ = tombstone vector คืน
+    //  ถือ lock ของ index จนจบ search เลยไม่เห็น vector ที่ยังไม่มี metadata
+    //  (crash ระหว่างนี้ -> reconcile ตอน start จะ tombstone vector ที่ไม่มี metadata ให้)
+    let mut index = coll.index.write().await;
+    //  collection ถูก drop ระหว่างรอ lock -> ไม่เขียนไฟล์ (directory ถูกลบไปแล้ว)
+    if coll.is_dropped() {
+        return res_error_msg(format!("collection {} not found", coll.name));
+    }
+    if index.dim() != embedding_vec.len() {
+        return res_error_msg("index dim mismatch with embedding dim");
     }
+    let id = match index.append(&embedding_vec) {
+        Ok(id) => id,
+        Err(e) => return res_error_msg(format!("index append error: {}", e)),
+    };
+    ordered.insert("id".to_string(), Value::Number(id.into()));
 
-    if !payload_fields.is_subset(&allowed_fields) {
-        return res_error_msg("payload contains unexpected fields");
+    let written = match serde_json::to_value(ordered) {
+        Ok(doc) => coll
+            .documents
+            .write()
+            .await
+            .put(id, &doc)
+            .map_err(|e| format!("write file error: {}", e)),
+        Err(e) => Err(format!("serialize payload error: {}", e)),
+    };
+    if let Err(msg) = written {
+        if let Err(e) = index.delete(id) {
+            return res_error_msg(format!(
+                "{} (rollback of vector {} failed: {}, it is removed on next start)",
+                msg, id, e
+            ));
+        }
+        return res_error_msg(msg);
     }
 
-    //  สร้าง embedding จากฟิลด์หลัก
-    let text = build_embedding_text(&payload);
-    let emb = {
-        let mut embedder = state.embedder.lock().await;
-        match embedder.embed(vec![text], None) {
+    res_success(serde_json::json!({ "message": "create successful", "id": id }))
+}
+
+pub async fn train_index(CollectionParam(coll): CollectionParam) -> impl IntoResponse {
+    let mut index = coll.index.clone().write_owned().await;
+    if coll.is_dropped() {
+        return res_error_msg(format!("collection {} not found", coll.name));
+    }
+    match tokio::task::spawn_blocking(move || index.train()).await {
+        Ok(Ok(report)) => res_success(report),
+        Ok(Err(e)) => res_error_msg(format!("index train error: {}", e)),
+        Err(e) => res_error_msg(format!("index train error: {}", e)),
+    }
+}
+
+pub async fn update_data(
+    CollectionParam(coll): CollectionParam,
+    json: Result<Json<Value>, axum::extract::rejection::JsonRejection>,
+) -> impl IntoResponse {
+    let payload = match json {
+        Ok(Json(value)) => value,
+        Err(err) => return res_error(err),
+    };
+
+    //  payload ต้องเป็น object และมี id ของ document ที่จะแก้
+    let obj = match payload.as_object() {
+        Some(o) => o,
+        None => return res_error_msg("payload must be a JSON object"),
+    };
+
+    let id = match obj.get("id").and_then(doc_store::parse_u64) {
+        Some(id) => id,
+        None => return res_error_msg("id is required"),
+    };
+
+    update_fields(&coll, id, obj).await
+}
+
+/// เหมือน update-data แต่ id มาจาก path (`PATCH /collections/{name}/documents/{id}`)
+pub async fn update_document(
+    CollectionParam(coll): CollectionParam,
+    UrlPath(DocumentPath { id }): UrlPath<DocumentPath>,
+    json: Result<Json<Value>, axum::extract::rejection::JsonRejection>,
+) -> impl IntoResponse {
+    let payload = match json {
+        Ok(Json(value)) => value,
+        Err(err) => return res_error(err),
+    };
+
+    let obj = match payload.as_object() {
+        Some(o) => o,
+        None => return res_error_msg("payload must be a JSON object"),
+    };
+
+    if obj
+        .get("id")
+        .is_some_and(|v| doc_store::parse_u64(v) != Some(id))
+    {
+        return res_error_msg("id in payload does not match the path");
+    }
+
+    update_fields(&coll, id, obj).await
+}
+
+// จำนวนครั้งที่ update ลอง merge + embed ใหม่เมื่อ document ถูกแก้ระหว่าง embed
+const UPDATE_ATTEMPTS: usize = 3;
+
+/// แก้ field ของ document `id` (ส่งมาเฉพาะ field ที่จะแก้) แล้ว embed ใหม่ถ้าข้อความเปลี่ยน
+async fn update_fields(coll: &Collection, id: u64, obj: &Map<String, Value>) -> Response {
+    if obj.contains_key("embedding") {
+        return res_error_msg("do not provide 'embedding' (server will generate it)");
+    }
+
+    //  embed นอก lock (search ไม่ต้องรอ model) แล้วถือ lock ของ index ตั้งแต่เช็คว่า document
+    //  ยังเป็นตัวที่ใช้ merge จนเขียน metadata เสร็จ (เหมือน create_data)
+    //  update อื่นแทรกระหว่าง embed -> merge + embed ใหม่จาก document ล่าสุด
+    let mut attempts = 0;
+    let (mut index, json_value, embedding) = loop {
+        let current = match coll.documents.read().await.get(id) {
+            Ok(Some(v)) => v,
+            Ok(None) => return res_error_msg(format!("id {} not found", id)),
+            Err(e) => return res_error_msg(format!("read documents error: {}", e)),
+        };
+        let json_value = match merge_update(coll, id, &current, obj) {
             Ok(v) => v,
-            Err(e) => return res_error_msg(format!("embedding error: {}", e)),
+            Err(res) => return res,
+        };
+
+        //  embed ใหม่เฉพาะเมื่อข้อความที่ใช้ทำ embedding เปลี่ยน
+        let text = coll.template.render(&json_value);
+        let embedding = if text != coll.template.render(&current) {
+            let mut embedder = coll.embedder.lock().await;
+            match embedder.embed(vec![text], None) {
+                Ok(v) => Some(v.into_iter().next().unwrap_or_default()),
+                Err(e) => return res_error_msg(format!("embedding error: {}", e)),
+            }
+        } else {
+            None
+        };
+
+        let index = coll.index.write().await;
+        if coll.is_dropped() {
+            return res_error_msg(format!("collection {} not found", coll.name));
+        }
+        match coll.documents.read().await.get(id) {
+            Ok(Some(latest)) if latest == current => break (index, json_value, embedding),
+            Ok(Some(_)) => {}
+            Ok(None) => return res_error_msg(format!("id {} not found", id)),
+            Err(e) => return res_error_msg(format!("read documents error: {}", e)),
+        }
+        attempts += 1;
+        if attempts == UPDATE_ATTEMPTS {
+            return res_error_msg(format!(
+                "id {} is being updated by other requests, try again",
+                id
+            ));
         }
     };
-    let embedding_vec = emb.get(0).cloned().unwrap_or_default();
 
-    //  append vector ลง FlatIndex -> ได้ id
-    let id = {
-        let index = state.index.lock().await;
+    //  vector เดิม ไว้ใส่คืนถ้าเขียน metadata ไม่ได้
+    let re_embedded = embedding.is_some();
+    let mut old_vec = None;
+    if let Some(embedding_vec) = embedding {
         if index.dim() != embedding_vec.len() {
             return res_error_msg("index dim mismatch with embedding dim");
         }
-        match index.append(&embedding_vec) {
-            Ok(id) => id,
-            Err(e) => return res_error_msg(format!("index append error: {}", e)),
+        old_vec = match index.vectors(&[id]) {
+            Ok(mut v) => v.pop().flatten(),
+            Err(e) => return res_error_msg(format!("index read error: {}", e)),
+        };
+        if old_vec.is_none() {
+            return res_error_msg(format!("id {} not found in index", id));
         }
-    };
+        match index.replace(id, &embedding_vec) {
+            Ok(true) => {}
+            Ok(false) => return res_error_msg(format!("id {} not found in index", id)),
+            Err(e) => return res_error_msg(format!("index replace error: {}", e)),
+        }
+    }
+
+    //  เขียน metadata ใหม่ ไม่ได้ = ใส่ vector เดิมคืน
+    if let Err(e) = coll.documents.write().await.put(id, &json_value) {
+        if let Some(old) = old_vec {
+            if let Err(re) = index.replace(id, &old) {
+                return res_error_msg(format!(
+                    "write file error: {} (rollback of vector {} failed: {}, run `backend reindex`)",
+                    e, id, re
+                ));
+            }
+        }
+        return res_error_msg(format!("write file error: {}", e));
+    }
+    drop(index);
+
+    res_success(serde_json::json!({
+        "message": "update successful",
+        "id": id,
+        "re_embedded": re_embedded,
+    }))
+}
+
+// ส่งมาเฉพาะ field ที่จะแก้ -> รวมกับ document เดิมแล้วเช็คทั้ง document ตาม schema
+#[allow(clippy::result_large_err)]
+fn merge_update(
+    coll: &Collection,
+    id: u64,
+    current: &Value,
+    obj: &Map<String, Value>,
+) -> Result<Value, Response> {
+    let mut merged: Map<String, Value> = coll
+        .schema
+        .fields
+        .iter()
+        .filter_map(|f| Some((f.name.clone(), current.get(&f.name)?.clone())))
+        .collect();
+    merged.extend(
+        obj.iter()
+            .filter(|(k, _)| k.as_str() != "id")
+            .map(|(k, v)| (k.clone(), v.clone())),
+    );
+    let fields = coll.schema.validate(&merged).map_err(res_field_errors)?;
 
-    //  สร้าง payload สำหรับ JSONL: ใส่ฟิลด์ปกติ + id (ไม่เก็บ embedding แล้ว)
     let mut ordered = IndexMap::new();
     ordered.insert("id".to_string(), Value::Number(id.into()));
+    ordered.extend(fields);
 
-    for f in &model_fields {
-        if f == "embedding" {
-            continue;
-        }
-        if f == "id" {
-            continue;
-        }
-        ordered.insert(f.clone(), payload.get(f).cloned().unwrap_or(Value::Null));
-    }
+    serde_json::to_value(ordered)
+        .map_err(|e| res_error_msg(format!("serialize payload error: {}", e)))
+}
 
-    let json_value = match serde_json::to_value(ordered) {
-        Ok(v) => v,
-        Err(e) => return res_error_msg(format!("serialize payload error: {}", e)),
-    };
+pub async fn delete_data(
+    CollectionParam(coll): CollectionParam,
+    Json(payload): Json<DeleteRequest>,
+) -> impl IntoResponse {
+    delete_by_id(&coll, payload.id).await
+}
 
-    // --- write JSONL (append one line per object) ---
-    let json_path = "src/data/reviews.jsonl";
+pub async fn delete_document(
+    CollectionParam(coll): CollectionParam,
+    UrlPath(DocumentPath { id }): UrlPath<DocumentPath>,
+) -> impl IntoResponse {
+    delete_by_id(&coll, id).await
+}
 
-    if let Err(err) = std::fs::create_dir_all("src/data") {
-        return res_error(err);
+async fn delete_by_id(coll: &Collection, id: u64) -> Response {
+    //  tombstone ใน index ก่อน (index เป็นตัวบอกว่ามี id นี้อยู่จริง)
+    //  ถือ lock ของ index จนลบ metadata เสร็จ -> drop collection แทรกกลางไม่ได้
+    let mut index = coll.index.write().await;
+    if coll.is_dropped() {
+        return res_error_msg(format!("collection {} not found", coll.name));
     }
+    //  ไม่มี vector แต่มี metadata (index หาย/ขาด record, reconcile เก็บไว้) -> ลบ metadata อย่างเดียว
+    match index.delete(id) {
+        Ok(true) => {}
+        Ok(false) => match coll.documents.read().await.get(id) {
+            Ok(Some(_)) => {}
+            Ok(None) => return res_error_msg(format!("id {} not found", id)),
+            Err(e) => return res_error_msg(format!("read documents error: {}", e)),
+        },
+        Err(e) => return res_error_msg(format!("index delete error: {}", e)),
+    }
+
+    //  ลบ metadata -> ซ่อนจาก search
+    if let Err(e) = coll.documents.write().await.delete(id) {
+        return res_error_msg(format!("write file error: {}", e));
+    }
+    drop(index);
 
-    let line = match serde_json::to_string(&json_value) {
-        Ok(s) => s,
-        Err(e) => return res_error_msg(format!("serialize error: {}", e)),
+    res_success(serde_json::json!({ "message": "delete successful", "id": id }))
+}
+
+pub async fn compact_data(CollectionParam(coll): CollectionParam) -> impl IntoResponse {
+    let mut index = coll.index.clone().write_owned().await;
+    if coll.is_dropped() {
+        return res_error_msg(format!("collection {} not found", coll.name));
+    }
+    //  คืน lock ของ index กลับมาถือไว้จน compact documents เสร็จ
+    let compacted = tokio::task::spawn_blocking(move || {
+        let report = index.compact();
+        (index, report)
+    })
+    .await;
+    let (index, index_report) = match compacted {
+        Ok((index, Ok(report))) => (index, report),
+        Ok((_, Err(e))) => return res_error_msg(format!("index compact error: {}", e)),
+        Err(e) => return res_error_msg(format!("index compact error: {}", e)),
     };
 
-    let mut file = match OpenOptions::new().create(true).append(true).open(json_path) {
-        Ok(f) => f,
-        Err(e) => return res_error_msg(format!("open file error: {}", e)),
+    let documents_report = match coll.documents.write().await.compact() {
+        Ok(report) => report,
+        Err(e) => return res_error_msg(format!("document compact error: {}", e)),
     };
+    drop(index);
 
-    if let Err(e) = writeln!(file, "{}", line) {
-        return res_error_msg(format!("write file error: {}", e));
+    res_success(serde_json::json!({
+        "index": index_report,
+        "documents": documents_report,
+    }))
+}
+
+pub async fn list_collections(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    let mut infos = Vec::new();
+    for collection in state.collections.list().await {
+        infos.push(collection.info().await);
     }
+    res_success(infos)
+}
 
-    res_success(serde_json::json!({ "message": "create successful", "id": id }))
+pub async fn create_collection(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<CreateCollectionRequest>,
+) -> impl IntoResponse {
+    //  เขียนไฟล์ + อาจต้องโหลด embedding model -> ทำใน blocking pool
+    let st = state.clone();
+    let created = match tokio::task::spawn_blocking(move || st.collections.create(payload)).await {
+        Ok(Ok(collection)) => collection,
+        Ok(Err(msg)) => return res_error_msg(msg),
+        Err(e) => return res_error_msg(format!("create collection error: {}", e)),
+    };
+
+    res_success(created.info().await)
+}
+
+pub async fn drop_collection(
+    State(state): State<Arc<AppState>>,
+    UrlPath(name): UrlPath<String>,
+) -> impl IntoResponse {
+    //  รอ request ที่ใช้ collection นี้อยู่ให้เสร็จก่อนลบไฟล์ -> ทำใน blocking pool
+    let st = state.clone();
+    let target = name.clone();
+    match tokio::task::spawn_blocking(move || st.collections.remove(&target)).await {
+        Ok(Ok(())) => {
+            res_success(serde_json::json!({ "message": "drop successful", "name": name }))
+        }
+        Ok(Err(msg)) => res_error_msg(msg),
+        Err(e) => res_error_msg(format!("drop collection error: {}", e)),
+    }
 }
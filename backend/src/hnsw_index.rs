@@ -0,0 +1,587 @@
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashSet};
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use crate::distance::Metric;
+use crate::flat_index::{FlatIndex, Query, VectorSpec};
+use crate::model::HnswSettings;
+use crate::vector_index::{CompactReport, SearchParams, VectorIndex};
+
+const MAGIC: &[u8; 4] = b"RVHN";
+const VERSION: u32 = 1;
+const NO_ENTRY: u32 = u32::MAX;
+
+/// HNSW graph over the vectors of an RVIX file.
+///
+/// The vectors themselves stay in `reviews.index` (through `FlatIndex`), the
+/// graph is saved next to it as `reviews.index.hnsw`. The graph file is only
+/// rewritten every `save_every` appends; records that are in the RVIX file but
+/// not in the saved graph are inserted again on `open_or_create`.
+pub struct HnswIndex {
+    store: FlatIndex,
+    graph_path: String,
+    m: usize,
+    ef_construction: usize,
+    ef_search: usize,
+    save_every: usize,
+
+    // node = slot ของ record ใน store -> links per level
+    links: Vec<Vec<Vec<u32>>>,
+    entry: u32,
+    max_level: usize,
+    unsaved: usize,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+struct Candidate {
+    dist: f32,
+    node: u32,
+}
+
+impl Eq for Candidate {}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.dist
+            .total_cmp(&other.dist)
+            .then_with(|| self.node.cmp(&other.node))
+    }
+}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl HnswIndex {
+    pub fn open_or_create(
+        index_path: impl Into<String>,
+        spec: &VectorSpec,
+        settings: &HnswSettings,
+    ) -> io::Result<Self> {
+        let index_path = index_path.into();
+        let graph_path = format!("{}.hnsw", index_path);
+        let store = FlatIndex::open_or_create(index_path, spec)?;
+
+        let mut index = Self {
+            store,
+            graph_path,
+            m: settings.m.max(2),
+            ef_construction: settings.ef_construction.max(1),
+            ef_search: settings.ef_search.max(1),
+            save_every: settings.save_every.max(1),
+            links: Vec::new(),
+            entry: NO_ENTRY,
+            max_level: 0,
+            unsaved: 0,
+        };
+
+        // โหลด graph ที่ save ไว้ ถ้าใช้ไม่ได้ก็ build ใหม่ทั้งหมด
+        let loaded = if Path::new(&index.graph_path).exists() {
+            index.load_graph().unwrap_or(0)
+        } else {
+            0
+        };
+        if loaded == 0 {
+            index.links.clear();
+            index.entry = NO_ENTRY;
+            index.max_level = 0;
+        }
+
+        // insert records ที่ยังไม่อยู่ใน graph
+        let total = index.store.len();
+        for node in loaded..total {
+            index.insert(node as u32);
+        }
+        if loaded < total {
+            index.save()?;
+        }
+
+        Ok(index)
+    }
+
+    pub fn dim(&self) -> usize {
+        self.store.dim()
+    }
+
+    pub fn metric(&self) -> Metric {
+        self.store.metric()
+    }
+
+    pub fn template(&self) -> u32 {
+        self.store.template()
+    }
+
+    pub fn set_template(&mut self, template: u32) -> io::Result<()> {
+        self.store.set_template(template)
+    }
+
+    pub fn append(&mut self, vec: &[f32]) -> io::Result<u64> {
+        let id = self.store.append(vec)?;
+        self.insert((self.store.len() - 1) as u32);
+
+        self.unsaved += 1;
+        if self.unsaved >= self.save_every {
+            self.save()?;
+        }
+
+        Ok(id)
+    }
+
+    pub fn search(
+        &self,
+        query: &[f32],
+        top_k: usize,
+        params: &SearchParams,
+    ) -> io::Result<Vec<(u64, f32)>> {
+        if query.len() != self.dim() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "dimension mismatch",
+            ));
+        }
+        if top_k == 0 || self.entry == NO_ENTRY {
+            return Ok(vec![]);
+        }
+
+        let q = self.store.prepare(query);
+        let mut ep = self.entry;
+        for level in (1..=self.max_level).rev() {
+            ep = self.greedy_closest(&q, ep, level);
+        }
+
+        let nodes = self.links.len();
+        let mut ef = self.ef_search.max(top_k);
+        let hits = loop {
+            let found = self.search_layer(&q, &[ep], ef, 0);
+
+            // node ที่ถูกลบ/ไม่ผ่าน filter ยังใช้เดินใน graph ได้ แต่ไม่คืนเป็นผลลัพธ์
+            let hits: Vec<(u64, f32)> = found
+                .into_iter()
+                .filter(|c| self.store.is_live(c.node as usize))
+                .map(|c| (self.store.id_at(c.node as usize), c.dist))
+                .filter(|&(id, _)| params.allows(id))
+                .take(top_k)
+                .collect();
+
+            // node ที่ถูกลบกิน beam จนได้ไม่ครบ k -> ขยาย ef จนครอบทั้ง graph
+            // (มี filter ไม่ต้องขยาย หา exact จาก id ที่ผ่าน filter ด้านล่างถูกกว่า)
+            if hits.len() >= top_k || ef >= nodes || params.allow.is_some() {
+                break hits;
+            }
+            ef = (ef * 2).min(nodes);
+        };
+        if hits.len() >= top_k {
+            return Ok(hits);
+        }
+
+        // filter แคบ หรือ graph ขาดจนเดินไม่ถึงทุก node -> หา exact แทน
+        match &params.allow {
+            Some(allow) => Ok(self.store.search_ids(&q, top_k, allow)),
+            None => self.store.search_with(query, top_k, params),
+        }
+    }
+
+    /// radius search แบบ exact บน store (graph/list ไม่ช่วยหา "ทุกตัว" ในรัศมี)
+    pub fn search_range(
+        &self,
+        query: &[f32],
+        max_distance: f32,
+        cap: Option<usize>,
+        params: &SearchParams,
+    ) -> io::Result<Vec<(u64, f32)>> {
+        self.store.search_range(query, max_distance, cap, params)
+    }
+
+    pub fn ids(&self) -> Vec<u64> {
+        self.store.ids()
+    }
+
+    pub fn is_deleted(&self, id: u64) -> bool {
+        self.store.is_deleted(id)
+    }
+
+    pub fn vectors(&self, ids: &[u64]) -> io::Result<Vec<Option<Vec<f32>>>> {
+        self.store.vectors(ids)
+    }
+
+    pub fn delete(&mut self, id: u64) -> io::Result<bool> {
+        self.store.delete(id)
+    }
+
+    /// แทน vector ของ `id` แล้วหา neighbor ใหม่ให้ node นั้น
+    ///
+    /// link ขาเข้าจาก neighbor เดิมยังอยู่ (ระยะคำนวณใหม่ทุกครั้งอยู่แล้ว
+    /// เลยไม่ทำให้ผลผิด แค่ graph อาจด้อยลงเล็กน้อยจนกว่าจะ compact)
+    pub fn replace(&mut self, id: u64, vec: &[f32]) -> io::Result<bool> {
+        let node = match self.store.slot_of(id) {
+            Some(slot) => slot as u32,
+            None => return Ok(false),
+        };
+        self.store.replace(id, vec)?;
+
+        let level = self.links[node as usize].len() - 1;
+        self.connect(node, level);
+
+        self.unsaved += 1;
+        if self.unsaved >= self.save_every {
+            self.save()?;
+        }
+
+        Ok(true)
+    }
+
+    /// compact RVIX แล้ว build graph ใหม่ (node = slot ซึ่งเปลี่ยนไปแล้ว)
+    pub fn compact(&mut self) -> io::Result<CompactReport> {
+        let report = self.store.compact()?;
+        if report.records_removed == 0 {
+            return Ok(report);
+        }
+
+        self.links.clear();
+        self.entry = NO_ENTRY;
+        self.max_level = 0;
+        for node in 0..self.store.len() {
+            self.insert(node as u32);
+        }
+        self.save()?;
+
+        Ok(report)
+    }
+
+    /// เขียน graph ลงไฟล์ (เขียน tmp แล้ว rename กันไฟล์ครึ่งๆ)
+    pub fn save(&mut self) -> io::Result<()> {
+        let tmp_path = format!("{}.tmp", self.graph_path);
+        {
+            let f = File::create(&tmp_path)?;
+            let mut w = BufWriter::new(f);
+
+            w.write_all(MAGIC)?;
+            w.write_u32::<LittleEndian>(VERSION)?;
+            w.write_u32::<LittleEndian>(self.dim() as u32)?;
+            w.write_u32::<LittleEndian>(self.m as u32)?;
+            w.write_u64::<LittleEndian>(self.links.len() as u64)?;
+            w.write_u32::<LittleEndian>(self.entry)?;
+            w.write_u32::<LittleEndian>(self.max_level as u32)?;
+
+            for (node, levels) in self.links.iter().enumerate() {
+                w.write_u64::<LittleEndian>(self.store.id_at(node))?;
+                w.write_u8(levels.len() as u8)?;
+                for neighbors in levels {
+                    w.write_u32::<LittleEndian>(neighbors.len() as u32)?;
+                    for &nb in neighbors {
+                        w.write_u32::<LittleEndian>(nb)?;
+                    }
+                }
+            }
+
+            w.flush()?;
+            w.get_ref().sync_all()?;
+        }
+        fs::rename(&tmp_path, &self.graph_path)?;
+
+        self.unsaved = 0;
+        Ok(())
+    }
+
+    /// โหลด graph แล้วคืนจำนวน node ที่ใช้ได้ (0 = ต้อง build ใหม่)
+    fn load_graph(&mut self) -> io::Result<usize> {
+        let f = File::open(&self.graph_path)?;
+        let mut r = BufReader::new(f);
+
+        let mut magic = [0u8; 4];
+        r.read_exact(&mut magic)?;
+        if &magic != MAGIC || r.read_u32::<LittleEndian>()? != VERSION {
+            return Ok(0);
+        }
+        let dim = r.read_u32::<LittleEndian>()? as usize;
+        let m = r.read_u32::<LittleEndian>()? as usize;
+        let count = r.read_u64::<LittleEndian>()? as usize;
+        if dim != self.dim() || m != self.m || count > self.store.len() {
+            return Ok(0);
+        }
+        let entry = r.read_u32::<LittleEndian>()?;
+        if count > 0 && entry as usize >= count {
+            return Ok(0);
+        }
+        let max_level = r.read_u32::<LittleEndian>()? as usize;
+
+        let mut links = Vec::with_capacity(count);
+        for node in 0..count {
+            let id = r.read_u64::<LittleEndian>()?;
+            if id != self.store.id_at(node) {
+                return Ok(0);
+            }
+            let level_count = r.read_u8()? as usize;
+            let mut levels = Vec::with_capacity(level_count);
+            for _ in 0..level_count {
+                let n = r.read_u32::<LittleEndian>()? as usize;
+                let mut neighbors = Vec::with_capacity(n);
+                for _ in 0..n {
+                    let nb = r.read_u32::<LittleEndian>()?;
+                    if nb as usize >= count {
+                        return Ok(0);
+                    }
+                    neighbors.push(nb);
+                }
+                levels.push(neighbors);
+            }
+            links.push(levels);
+        }
+
+        self.links = links;
+        self.entry = if count == 0 { NO_ENTRY } else { entry };
+        self.max_level = max_level;
+        Ok(count)
+    }
+
+    fn distance(&self, query: &Query, node: u32) -> f32 {
+        self.store.score(query, node as usize)
+    }
+
+    fn max_links(&self, level: usize) -> usize {
+        if level == 0 {
+            self.m * 2
+        } else {
+            self.m
+        }
+    }
+
+    /// สุ่ม level จาก id (deterministic เพื่อให้ rebuild แล้วได้ graph เดิม)
+    fn random_level(&self, id: u64) -> usize {
+        // splitmix64
+        let mut z = id.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^= z >> 31;
+
+        let u = ((z >> 11) as f64 + 1.0) / (1u64 << 53) as f64;
+        let ml = 1.0 / (self.m as f64).ln();
+        ((-u.ln() * ml) as usize).min(16)
+    }
+
+    fn insert(&mut self, node: u32) {
+        let level = self.random_level(self.store.id_at(node as usize));
+        self.links.push(vec![Vec::new(); level + 1]);
+
+        if self.entry == NO_ENTRY {
+            self.entry = node;
+            self.max_level = level;
+            return;
+        }
+
+        self.connect(node, level);
+
+        if level > self.max_level {
+            self.entry = node;
+            self.max_level = level;
+        }
+    }
+
+    /// หา neighbor ของ `node` ทุก level ตั้งแต่ `level` ลงมา แล้วต่อ link สองทาง
+    fn connect(&mut self, node: u32, level: usize) {
+        let query = self.store.prepare(&self.store.vector_at(node as usize));
+
+        let mut ep = self.entry;
+        for lc in (level + 1..=self.max_level).rev() {
+            ep = self.greedy_closest(&query, ep, lc);
+        }
+
+        let mut eps = vec![ep];
+        for lc in (0..=level.min(self.max_level)).rev() {
+            let candidates = self.search_layer(&query, &eps, self.ef_construction, lc);
+            let max_links = self.max_links(lc);
+
+            let neighbors: Vec<u32> = candidates
+                .iter()
+                .filter(|c| c.node != node)
+                .take(self.m)
+                .map(|c| c.node)
+                .collect();
+            for &nb in &neighbors {
+                // replace ต่อ node เดิมซ้ำ -> link ขาเข้าที่มีอยู่แล้วไม่ต้องเพิ่ม
+                if self.links[nb as usize][lc].contains(&node) {
+                    continue;
+                }
+                self.links[nb as usize][lc].push(node);
+                if self.links[nb as usize][lc].len() > max_links {
+                    self.prune(nb, lc, max_links);
+                }
+            }
+            self.links[node as usize][lc] = neighbors;
+
+            eps = candidates.iter().map(|c| c.node).collect();
+        }
+    }
+
+    /// เก็บเฉพาะ neighbor ที่ใกล้ที่สุด `max_links` ตัว
+    fn prune(&mut self, node: u32, level: usize, max_links: usize) {
+        let base = self.store.prepare(&self.store.vector_at(node as usize));
+        let mut scored: Vec<Candidate> = self.links[node as usize][level]
+            .iter()
+            .map(|&nb| Candidate {
+                dist: self.distance(&base, nb),
+                node: nb,
+            })
+            .collect();
+        scored.sort();
+        // graph ที่ save ไว้อาจมี link ซ้ำ -> ตัดซ้ำออกก่อนเลือก max_links ตัว
+        scored.dedup_by_key(|c| c.node);
+        scored.truncate(max_links);
+        self.links[node as usize][level] = scored.into_iter().map(|c| c.node).collect();
+    }
+
+    fn greedy_closest(&self, query: &Query, start: u32, level: usize) -> u32 {
+        let mut best = start;
+        let mut best_dist = self.distance(query, start);
+        loop {
+            let mut changed = false;
+            for &nb in &self.links[best as usize][level] {
+                let d = self.distance(query, nb);
+                if d < best_dist {
+                    best = nb;
+                    best_dist = d;
+                    changed = true;
+                }
+            }
+            if !changed {
+                return best;
+            }
+        }
+    }
+
+    /// beam search ใน level เดียว คืน candidate เรียงจากใกล้ไปไกล
+    fn search_layer(&self, query: &Query, eps: &[u32], ef: usize, level: usize) -> Vec<Candidate> {
+        let mut visited: HashSet<u32> = HashSet::new();
+        // min-heap ของ candidate ที่ยังไม่ขยาย, max-heap ของผลลัพธ์
+        let mut candidates: BinaryHeap<std::cmp::Reverse<Candidate>> = BinaryHeap::new();
+        let mut found: BinaryHeap<Candidate> = BinaryHeap::new();
+
+        for &ep in eps {
+            if visited.insert(ep) {
+                let c = Candidate {
+                    dist: self.distance(query, ep),
+                    node: ep,
+                };
+                candidates.push(std::cmp::Reverse(c));
+                found.push(c);
+            }
+        }
+        while found.len() > ef {
+            found.pop();
+        }
+
+        while let Some(std::cmp::Reverse(current)) = candidates.pop() {
+            if let Some(worst) = found.peek() {
+                if found.len() >= ef && current.dist > worst.dist {
+                    break;
+                }
+            }
+
+            for &nb in &self.links[current.node as usize][level] {
+                if !visited.insert(nb) {
+                    continue;
+                }
+                let c = Candidate {
+                    dist: self.distance(query, nb),
+                    node: nb,
+                };
+                let worse_than_all = found
+                    .peek()
+                    .map(|w| found.len() >= ef && c.dist >= w.dist)
+                    .unwrap_or(false);
+                if worse_than_all {
+                    continue;
+                }
+                candidates.push(std::cmp::Reverse(c));
+                found.push(c);
+                if found.len() > ef {
+                    found.pop();
+                }
+            }
+        }
+
+        found.into_sorted_vec()
+    }
+}
+
+impl Drop for HnswIndex {
+    fn drop(&mut self) {
+        if self.unsaved > 0 {
+            let _ = self.save();
+        }
+    }
+}
+
+impl VectorIndex for HnswIndex {
+    fn dim(&self) -> usize {
+        HnswIndex::dim(self)
+    }
+
+    fn metric(&self) -> Metric {
+        HnswIndex::metric(self)
+    }
+
+    fn template(&self) -> u32 {
+        HnswIndex::template(self)
+    }
+
+    fn set_template(&mut self, template: u32) -> io::Result<()> {
+        HnswIndex::set_template(self, template)
+    }
+
+    fn append(&mut self, vec: &[f32]) -> io::Result<u64> {
+        HnswIndex::append(self, vec)
+    }
+
+    fn delete(&mut self, id: u64) -> io::Result<bool> {
+        HnswIndex::delete(self, id)
+    }
+
+    fn replace(&mut self, id: u64, vec: &[f32]) -> io::Result<bool> {
+        HnswIndex::replace(self, id, vec)
+    }
+
+    fn compact(&mut self) -> io::Result<CompactReport> {
+        HnswIndex::compact(self)
+    }
+
+    fn search(&self, query: &[f32], top_k: usize) -> io::Result<Vec<(u64, f32)>> {
+        HnswIndex::search(self, query, top_k, &SearchParams::default())
+    }
+
+    fn search_with(
+        &self,
+        query: &[f32],
+        top_k: usize,
+        params: &SearchParams,
+    ) -> io::Result<Vec<(u64, f32)>> {
+        HnswIndex::search(self, query, top_k, params)
+    }
+
+    fn search_range(
+        &self,
+        query: &[f32],
+        max_distance: f32,
+        cap: Option<usize>,
+        params: &SearchParams,
+    ) -> io::Result<Vec<(u64, f32)>> {
+        HnswIndex::search_range(self, query, max_distance, cap, params)
+    }
+
+    fn ids(&self) -> Vec<u64> {
+        HnswIndex::ids(self)
+    }
+
+    fn is_deleted(&self, id: u64) -> bool {
+        HnswIndex::is_deleted(self, id)
+    }
+
+    fn vectors(&self, ids: &[u64]) -> io::Result<Vec<Option<Vec<f32>>>> {
+        HnswIndex::vectors(self, ids)
+    }
+}
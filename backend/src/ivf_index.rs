@@ -0,0 +1,497 @@
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use crate::distance::{normalized, squared_l2, Metric, TopK};
+use crate::flat_index::{FlatIndex, VectorSpec};
+use crate::kmeans;
+use crate::model::IvfSettings;
+use crate::vector_index::{CompactReport, SearchParams, TrainReport, VectorIndex};
+
+const MAGIC: &[u8; 4] = b"RVIF";
+const VERSION: u32 = 1;
+
+/// Inverted-file index (IVF-Flat) over the vectors of an RVIX file.
+///
+/// `reviews.index.ivf` holds the trained centroids followed by one
+/// `(id, list)` assignment per record. New records are assigned to their
+/// nearest centroid and appended to the same file. Until `train` has been run
+/// the index has no lists and `search` scans every record like `FlatIndex`.
+/// Lists changed by `replace` are only rewritten every `save_every` moves (and
+/// on drop); a move lost in a crash leaves the node in its old list, where it is
+/// still found, just probed less precisely.
+pub struct IvfIndex {
+    store: FlatIndex,
+    ivf_path: String,
+    nlist: usize,
+    nprobe: usize,
+    iterations: usize,
+    train_sample: usize,
+    save_every: usize,
+
+    // node = slot ของ record ใน store
+    // ว่าง = ยังไม่ train
+    centroids: Vec<f32>,
+    lists: Vec<Vec<u32>>,
+    // จำนวน node ที่ย้าย list แล้วยังไม่ได้เขียนลง .ivf
+    unsaved: usize,
+}
+
+impl IvfIndex {
+    pub fn open_or_create(
+        index_path: impl Into<String>,
+        spec: &VectorSpec,
+        settings: &IvfSettings,
+    ) -> io::Result<Self> {
+        let index_path = index_path.into();
+        let ivf_path = format!("{}.ivf", index_path);
+        let store = FlatIndex::open_or_create(index_path, spec)?;
+
+        let mut index = Self {
+            store,
+            ivf_path,
+            nlist: settings.nlist.max(1),
+            nprobe: settings.nprobe.max(1),
+            iterations: settings.iterations,
+            train_sample: settings.train_sample.max(1),
+            save_every: settings.save_every.max(1),
+            centroids: Vec::new(),
+            lists: Vec::new(),
+            unsaved: 0,
+        };
+
+        if Path::new(&index.ivf_path).exists() {
+            index.load()?;
+        }
+
+        Ok(index)
+    }
+
+    pub fn dim(&self) -> usize {
+        self.store.dim()
+    }
+
+    pub fn metric(&self) -> Metric {
+        self.store.metric()
+    }
+
+    pub fn template(&self) -> u32 {
+        self.store.template()
+    }
+
+    pub fn set_template(&mut self, template: u32) -> io::Result<()> {
+        self.store.set_template(template)
+    }
+
+    pub fn is_trained(&self) -> bool {
+        !self.centroids.is_empty()
+    }
+
+    pub fn append(&mut self, vec: &[f32]) -> io::Result<u64> {
+        let node = self.store.len() as u32;
+        let id = self.store.append(vec)?;
+
+        if self.is_trained() {
+            let list = self.assign(vec);
+            self.lists[list].push(node);
+            self.append_assignments(&[(id, list as u32)])?;
+        }
+
+        Ok(id)
+    }
+
+    pub fn search(
+        &self,
+        query: &[f32],
+        top_k: usize,
+        params: &SearchParams,
+    ) -> io::Result<Vec<(u64, f32)>> {
+        if query.len() != self.dim() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "dimension mismatch",
+            ));
+        }
+        if top_k == 0 {
+            return Ok(vec![]);
+        }
+
+        if !self.is_trained() {
+            return self.store.search_with(query, top_k, params);
+        }
+
+        let q = self.store.prepare(query);
+        let mut best = TopK::new(top_k);
+        let nprobe = params
+            .nprobe
+            .unwrap_or(self.nprobe)
+            .clamp(1, self.lists.len());
+        for list in self.nearest_lists(query, nprobe) {
+            for &node in &self.lists[list] {
+                let slot = node as usize;
+                let id = self.store.id_at(slot);
+                if !self.store.is_live(slot) || !params.allows(id) {
+                    continue;
+                }
+                best.push(id, self.store.score(&q, slot));
+            }
+        }
+
+        let hits = best.into_sorted_vec();
+        // list ที่ probe มีผลที่ผ่าน filter ไม่ครบ k -> หา exact จาก id ที่ผ่าน filter แทน
+        match &params.allow {
+            Some(allow) if hits.len() < top_k => Ok(self.store.search_ids(&q, top_k, allow)),
+            _ => Ok(hits),
+        }
+    }
+
+    /// train centroids ใหม่ด้วย k-means แล้ว assign ทุก record ใหม่
+    pub fn train(&mut self) -> io::Result<TrainReport> {
+        let dim = self.dim();
+        let live: Vec<usize> = (0..self.store.len())
+            .filter(|&slot| self.store.is_live(slot))
+            .collect();
+        let n = live.len();
+        if n == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "cannot train an empty index",
+            ));
+        }
+
+        // sample แบบเว้นระยะเท่าๆ กันจาก record ที่ยังไม่ถูกลบ (cosine: row ใน store normalize แล้ว)
+        let sample_n = n.min(self.train_sample);
+        let mut sample = Vec::with_capacity(sample_n * dim);
+        for i in 0..sample_n {
+            sample.extend(self.store.vector_at(live[i * n / sample_n]));
+        }
+
+        let raw = kmeans::train(&sample, dim, self.nlist, self.iterations);
+        self.centroids = if self.store.metric().normalizes() {
+            raw.chunks_exact(dim).flat_map(normalized).collect()
+        } else {
+            raw
+        };
+
+        self.assign_all()?;
+
+        Ok(TrainReport {
+            records: n,
+            centroids: self.lists.len(),
+        })
+    }
+
+    /// radius search แบบ exact บน store (graph/list ไม่ช่วยหา "ทุกตัว" ในรัศมี)
+    pub fn search_range(
+        &self,
+        query: &[f32],
+        max_distance: f32,
+        cap: Option<usize>,
+        params: &SearchParams,
+    ) -> io::Result<Vec<(u64, f32)>> {
+        self.store.search_range(query, max_distance, cap, params)
+    }
+
+    pub fn ids(&self) -> Vec<u64> {
+        self.store.ids()
+    }
+
+    pub fn is_deleted(&self, id: u64) -> bool {
+        self.store.is_deleted(id)
+    }
+
+    pub fn vectors(&self, ids: &[u64]) -> io::Result<Vec<Option<Vec<f32>>>> {
+        self.store.vectors(ids)
+    }
+
+    pub fn delete(&mut self, id: u64) -> io::Result<bool> {
+        self.store.delete(id)
+    }
+
+    /// แทน vector ของ `id` แล้วย้าย node ไป list ของ centroid ที่ใกล้ที่สุดใหม่
+    pub fn replace(&mut self, id: u64, vec: &[f32]) -> io::Result<bool> {
+        let node = match self.store.slot_of(id) {
+            Some(slot) => slot as u32,
+            None => return Ok(false),
+        };
+        self.store.replace(id, vec)?;
+
+        if self.is_trained() {
+            let list = self.assign(vec);
+            if !self.lists[list].contains(&node) {
+                for l in &mut self.lists {
+                    l.retain(|&n| n != node);
+                }
+                self.lists[list].push(node);
+                // assignment เป็น append-only ตามลำดับ record -> เขียนไฟล์ใหม่ทุก save_every ครั้ง
+                self.unsaved += 1;
+                if self.unsaved >= self.save_every {
+                    self.save()?;
+                }
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// compact RVIX แล้ว assign ทุก record ใหม่ด้วย centroids เดิม
+    pub fn compact(&mut self) -> io::Result<CompactReport> {
+        let report = self.store.compact()?;
+        if report.records_removed > 0 && self.is_trained() {
+            self.assign_all()?;
+        }
+        Ok(report)
+    }
+
+    /// assign ทุก record เข้า list ของ centroid ที่ใกล้ที่สุด แล้วเขียน .ivf ใหม่
+    fn assign_all(&mut self) -> io::Result<()> {
+        let n = self.store.len();
+        self.lists = vec![Vec::new(); self.centroids.len() / self.dim()];
+        let mut assignments = Vec::with_capacity(n);
+        for node in 0..n {
+            let list = self.assign(&self.store.vector_at(node));
+            self.lists[list].push(node as u32);
+            assignments.push((self.store.id_at(node), list as u32));
+        }
+        self.write_all(&assignments)
+    }
+
+    /// เขียน assignment ปัจจุบันของทุก node ลง .ivf
+    pub fn save(&mut self) -> io::Result<()> {
+        let assignments = self.assignments();
+        self.write_all(&assignments)
+    }
+
+    /// list ที่ centroid ใกล้ `vec` ที่สุด (เหมือนตอน probe)
+    fn assign(&self, vec: &[f32]) -> usize {
+        self.nearest_lists(vec, 1)[0]
+    }
+
+    /// centroid มาจาก k-means แบบ squared L2 -> assign/probe ด้วย L2 ทุก metric
+    /// (cosine: normalize ก่อน ซึ่งได้ลำดับเดียวกับ cosine distance)
+    fn nearest_lists(&self, query: &[f32], nprobe: usize) -> Vec<usize> {
+        let query = if self.store.metric().normalizes() {
+            normalized(query)
+        } else {
+            query.to_vec()
+        };
+        let mut best = TopK::new(nprobe);
+        for (list, c) in self.centroids.chunks_exact(self.dim()).enumerate() {
+            best.push(list as u64, squared_l2(&query, c));
+        }
+        best.into_sorted_vec()
+            .into_iter()
+            .map(|(list, _)| list as usize)
+            .collect()
+    }
+
+    fn load(&mut self) -> io::Result<()> {
+        let dim = self.dim();
+        let f = File::open(&self.ivf_path)?;
+        let mut r = BufReader::new(f);
+
+        let mut magic = [0u8; 4];
+        r.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "bad ivf magic"));
+        }
+        if r.read_u32::<LittleEndian>()? != VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "unsupported ivf version",
+            ));
+        }
+        if r.read_u32::<LittleEndian>()? as usize != dim {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "dim mismatch with existing ivf file",
+            ));
+        }
+        let k = r.read_u32::<LittleEndian>()? as usize;
+        let mut centroids = vec![0f32; k * dim];
+        r.read_f32_into::<LittleEndian>(&mut centroids)?;
+        self.centroids = centroids;
+        self.lists = vec![Vec::new(); k];
+
+        // assignment ต้องตรงกับลำดับ record ใน RVIX
+        let mut consistent = true;
+        let mut node = 0usize;
+        loop {
+            let id = match r.read_u64::<LittleEndian>() {
+                Ok(v) => v,
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+                Err(e) => return Err(e),
+            };
+            let list = match r.read_u32::<LittleEndian>() {
+                Ok(v) => v as usize,
+                // assignment ที่เขียนไม่ครบ -> เขียนไฟล์ใหม่
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                    consistent = false;
+                    break;
+                }
+                Err(e) => return Err(e),
+            };
+            if node >= self.store.len() || self.store.id_at(node) != id || list >= k {
+                consistent = false;
+                break;
+            }
+            self.lists[list].push(node as u32);
+            node += 1;
+        }
+
+        if !consistent {
+            // assignment เพี้ยน -> assign ใหม่ทั้งหมดจาก centroids เดิม
+            self.lists = vec![Vec::new(); k];
+            node = 0;
+        }
+
+        let mut assignments = Vec::new();
+        for n in node..self.store.len() {
+            let list = self.assign(&self.store.vector_at(n));
+            self.lists[list].push(n as u32);
+            assignments.push((self.store.id_at(n), list as u32));
+        }
+
+        if !consistent {
+            self.write_all(&assignments)?;
+        } else if !assignments.is_empty() {
+            self.append_assignments(&assignments)?;
+        }
+
+        Ok(())
+    }
+
+    /// (id, list) ของทุก node เรียงตามลำดับ record
+    fn assignments(&self) -> Vec<(u64, u32)> {
+        let mut list_of = vec![0u32; self.store.len()];
+        for (list, nodes) in self.lists.iter().enumerate() {
+            for &node in nodes {
+                list_of[node as usize] = list as u32;
+            }
+        }
+        list_of
+            .into_iter()
+            .enumerate()
+            .map(|(node, list)| (self.store.id_at(node), list))
+            .collect()
+    }
+
+    /// เขียนไฟล์ .ivf ใหม่ทั้งไฟล์ (tmp แล้ว rename)
+    fn write_all(&mut self, assignments: &[(u64, u32)]) -> io::Result<()> {
+        let tmp_path = format!("{}.tmp", self.ivf_path);
+        {
+            let f = File::create(&tmp_path)?;
+            let mut w = BufWriter::new(f);
+
+            w.write_all(MAGIC)?;
+            w.write_u32::<LittleEndian>(VERSION)?;
+            w.write_u32::<LittleEndian>(self.dim() as u32)?;
+            w.write_u32::<LittleEndian>((self.centroids.len() / self.dim()) as u32)?;
+            for &c in &self.centroids {
+                w.write_f32::<LittleEndian>(c)?;
+            }
+            for &(id, list) in assignments {
+                w.write_u64::<LittleEndian>(id)?;
+                w.write_u32::<LittleEndian>(list)?;
+            }
+
+            w.flush()?;
+            w.get_ref().sync_all()?;
+        }
+        fs::rename(&tmp_path, &self.ivf_path)?;
+        self.unsaved = 0;
+        Ok(())
+    }
+
+    fn append_assignments(&self, assignments: &[(u64, u32)]) -> io::Result<()> {
+        let f = OpenOptions::new().append(true).open(&self.ivf_path)?;
+        let mut w = BufWriter::new(f);
+        for &(id, list) in assignments {
+            w.write_u64::<LittleEndian>(id)?;
+            w.write_u32::<LittleEndian>(list)?;
+        }
+        w.flush()
+    }
+}
+
+impl Drop for IvfIndex {
+    fn drop(&mut self) {
+        if self.unsaved > 0 {
+            let _ = self.save();
+        }
+    }
+}
+
+impl VectorIndex for IvfIndex {
+    fn dim(&self) -> usize {
+        IvfIndex::dim(self)
+    }
+
+    fn metric(&self) -> Metric {
+        IvfIndex::metric(self)
+    }
+
+    fn template(&self) -> u32 {
+        IvfIndex::template(self)
+    }
+
+    fn set_template(&mut self, template: u32) -> io::Result<()> {
+        IvfIndex::set_template(self, template)
+    }
+
+    fn append(&mut self, vec: &[f32]) -> io::Result<u64> {
+        IvfIndex::append(self, vec)
+    }
+
+    fn delete(&mut self, id: u64) -> io::Result<bool> {
+        IvfIndex::delete(self, id)
+    }
+
+    fn replace(&mut self, id: u64, vec: &[f32]) -> io::Result<bool> {
+        IvfIndex::replace(self, id, vec)
+    }
+
+    fn compact(&mut self) -> io::Result<CompactReport> {
+        IvfIndex::compact(self)
+    }
+
+    fn search(&self, query: &[f32], top_k: usize) -> io::Result<Vec<(u64, f32)>> {
+        IvfIndex::search(self, query, top_k, &SearchParams::default())
+    }
+
+    fn search_with(
+        &self,
+        query: &[f32],
+        top_k: usize,
+        params: &SearchParams,
+    ) -> io::Result<Vec<(u64, f32)>> {
+        IvfIndex::search(self, query, top_k, params)
+    }
+
+    fn train(&mut self) -> io::Result<TrainReport> {
+        IvfIndex::train(self)
+    }
+
+    fn search_range(
+        &self,
+        query: &[f32],
+        max_distance: f32,
+        cap: Option<usize>,
+        params: &SearchParams,
+    ) -> io::Result<Vec<(u64, f32)>> {
+        IvfIndex::search_range(self, query, max_distance, cap, params)
+    }
+
+    fn ids(&self) -> Vec<u64> {
+        IvfIndex::ids(self)
+    }
+
+    fn is_deleted(&self, id: u64) -> bool {
+        IvfIndex::is_deleted(self, id)
+    }
+
+    fn vectors(&self, ids: &[u64]) -> io::Result<Vec<Option<Vec<f32>>>> {
+        IvfIndex::vectors(self, ids)
+    }
+}
@@ -0,0 +1,360 @@
+use serde_json::{Map, Value};
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+
+use crate::doc_store::{parse_u64, DocumentStore};
+use crate::vector_index::CompactReport;
+
+// บรรทัด tombstone ใน JSONL: {"id": <id>, "_deleted": true}
+const TOMBSTONE_KEY: &str = "_deleted";
+
+fn is_tombstone(v: &Value) -> bool {
+    v.get(TOMBSTONE_KEY)
+        .and_then(Value::as_bool)
+        .unwrap_or(false)
+}
+
+/// Documents of `reviews.jsonl`, indexed once at startup. Only the byte range
+/// of each live line is kept in memory (`id -> (offset, len)`); documents are
+/// read back from the file on demand, so a search only touches its top-k lines.
+/// The values of the short fields filters use (`Schema::filter_fields`) are
+/// kept too, so a filter doesn't re-read the file.
+/// Every write goes through the store so the maps stay in sync with the file.
+#[derive(Debug)]
+pub struct JsonlStore {
+    path: String,
+    lines: HashMap<u64, (u64, usize)>,
+    fields: Vec<String>,
+    // id -> object ที่มีแค่ค่าของ `fields`
+    values: HashMap<u64, Value>,
+    // ความยาวไฟล์ = offset ของบรรทัดถัดไปที่ append
+    end: u64,
+}
+
+impl JsonlStore {
+    /// index ทุกบรรทัดของไฟล์ (ไฟล์ไม่มี = store ว่าง)
+    ///
+    /// record ที่มี tombstone ตามหลังจะไม่อยู่ใน map, บรรทัดที่ parse ไม่ได้ถูกข้าม
+    /// `fields` = field ที่เก็บค่าไว้ใน memory ให้ filter
+    pub fn open(json_path: impl Into<String>, fields: Vec<String>) -> io::Result<Self> {
+        let mut store = Self {
+            path: json_path.into(),
+            lines: HashMap::new(),
+            fields,
+            values: HashMap::new(),
+            end: 0,
+        };
+        store.reload()?;
+        Ok(store)
+    }
+
+    fn reload(&mut self) -> io::Result<()> {
+        let content = match fs::read(&self.path) {
+            Ok(c) => c,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e),
+        };
+
+        self.lines.clear();
+        self.values.clear();
+        let mut offset = 0u64;
+        for raw in content.split_inclusive(|&b| b == b'\n') {
+            let line_offset = offset;
+            offset += raw.len() as u64;
+
+            let v = match serde_json::from_slice::<Value>(raw) {
+                Ok(v) => v,
+                Err(_) => continue,
+            };
+            let Some(id) = v.get("id").and_then(parse_u64) else {
+                continue;
+            };
+            if is_tombstone(&v) {
+                self.lines.remove(&id);
+                self.values.remove(&id);
+            } else {
+                self.lines.insert(id, (line_offset, raw.len()));
+                self.values.insert(id, self.filter_values(&v));
+            }
+        }
+        // บรรทัดสุดท้ายไม่มี \n (เขียนค้างตอน crash/แก้มือ) -> ปิดบรรทัดก่อน append ต่อ
+        if content.last().is_some_and(|&b| b != b'\n') {
+            let mut file = OpenOptions::new().append(true).open(&self.path)?;
+            file.write_all(b"\n")?;
+            offset += 1;
+        }
+        self.end = offset;
+
+        Ok(())
+    }
+
+    fn filter_values(&self, doc: &Value) -> Value {
+        let values: Map<String, Value> = self
+            .fields
+            .iter()
+            .filter_map(|f| Some((f.clone(), doc.get(f)?.clone())))
+            .collect();
+        Value::Object(values)
+    }
+
+    /// จำนวน document ที่ยังไม่ถูกลบ
+    pub fn len(&self) -> usize {
+        self.lines.len()
+    }
+
+    pub fn contains(&self, id: u64) -> bool {
+        self.lines.contains_key(&id)
+    }
+
+    /// อ่าน document ของแต่ละ id ตามลำดับที่ขอ (None = ไม่มี/ถูกลบแล้ว)
+    pub fn get_many(&self, ids: &[u64]) -> io::Result<Vec<Option<Value>>> {
+        if !ids.iter().any(|id| self.contains(*id)) {
+            return Ok(vec![None; ids.len()]);
+        }
+
+        let mut f = File::open(&self.path)?;
+        let mut buf = Vec::new();
+        ids.iter()
+            .map(|id| {
+                let Some(&(offset, len)) = self.lines.get(id) else {
+                    return Ok(None);
+                };
+                buf.resize(len, 0);
+                f.seek(SeekFrom::Start(offset))?;
+                f.read_exact(&mut buf)?;
+                Ok(Some(serde_json::from_slice(&buf)?))
+            })
+            .collect()
+    }
+
+    /// อ่านทุก document ที่ยังไม่ถูกลบตามลำดับในไฟล์ แล้วเรียก `f(id, doc)`
+    pub fn scan(&self, f: &mut dyn FnMut(u64, &Value)) -> io::Result<()> {
+        let file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        let mut r = BufReader::new(file);
+
+        // map เป็นตัวตัดสินว่าบรรทัดไหนยังใช้อยู่ (บรรทัดเก่า/tombstone ถูกข้าม)
+        let mut line = Vec::new();
+        let mut offset = 0u64;
+        loop {
+            line.clear();
+            let n = r.read_until(b'\n', &mut line)?;
+            if n == 0 || offset >= self.end {
+                return Ok(());
+            }
+            let line_offset = offset;
+            offset += n as u64;
+
+            let Ok(v) = serde_json::from_slice::<Value>(&line) else {
+                continue;
+            };
+            let Some(id) = v.get("id").and_then(parse_u64) else {
+                continue;
+            };
+            if self.lines.get(&id).is_some_and(|&(o, _)| o == line_offset) {
+                f(id, &v);
+            }
+        }
+    }
+
+    /// document ใหม่ -> append 1 บรรทัด, id เดิม -> เขียนบรรทัดนั้นใหม่
+    pub fn put(&mut self, id: u64, value: &Value) -> io::Result<()> {
+        if self.contains(id) {
+            // เขียนทั้งไฟล์ใหม่ -> offset หลังบรรทัดนี้เลื่อนหมด, index ใหม่ทั้งไฟล์
+            replace(&self.path, id, value)?;
+            return self.reload();
+        }
+        let len = append(&self.path, value)?;
+        self.lines.insert(id, (self.end, len));
+        self.values.insert(id, self.filter_values(value));
+        self.end += len as u64;
+        Ok(())
+    }
+
+    /// tombstone document `id` คืน false ถ้าไม่มีหรือถูกลบไปแล้ว
+    pub fn delete(&mut self, id: u64) -> io::Result<bool> {
+        if !self.contains(id) {
+            return Ok(false);
+        }
+        let len = append_tombstone(&self.path, id)?;
+        self.lines.remove(&id);
+        self.values.remove(&id);
+        self.end += len as u64;
+        Ok(true)
+    }
+
+    /// เขียนไฟล์ใหม่โดยตัด document ที่ถูกลบออก แล้ว index ใหม่
+    pub fn compact(&mut self) -> io::Result<CompactReport> {
+        let report = compact(&self.path)?;
+        if report.records_removed > 0 {
+            self.reload()?;
+        }
+        Ok(report)
+    }
+}
+
+impl DocumentStore for JsonlStore {
+    fn len(&self) -> usize {
+        JsonlStore::len(self)
+    }
+
+    fn get_many(&self, ids: &[u64]) -> io::Result<Vec<Option<Value>>> {
+        JsonlStore::get_many(self, ids)
+    }
+
+    fn put(&mut self, id: u64, doc: &Value) -> io::Result<()> {
+        JsonlStore::put(self, id, doc)
+    }
+
+    fn delete(&mut self, id: u64) -> io::Result<bool> {
+        JsonlStore::delete(self, id)
+    }
+
+    fn scan(&self, f: &mut dyn FnMut(u64, &Value)) -> io::Result<()> {
+        JsonlStore::scan(self, f)
+    }
+
+    fn filter_fields(&self) -> &[String] {
+        &self.fields
+    }
+
+    fn scan_fields(&self, f: &mut dyn FnMut(u64, &Value)) {
+        for (id, values) in &self.values {
+            f(*id, values);
+        }
+    }
+
+    fn compact(&mut self) -> io::Result<CompactReport> {
+        JsonlStore::compact(self)
+    }
+}
+
+// append 1 record เป็น 1 บรรทัด คืนจำนวน byte ที่เขียน (รวม \n)
+// ต้องลง disk ก่อนตอบว่าสร้างแล้ว (ไม่งั้น crash แล้ว document หายทั้งที่ vector อยู่)
+fn append(json_path: &str, value: &Value) -> io::Result<usize> {
+    let line = format!("{}\n", serde_json::to_string(value)?);
+    append_line(json_path, &line)?.sync_data()?;
+    Ok(line.len())
+}
+
+fn append_line(json_path: &str, line: &str) -> io::Result<File> {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(json_path)?;
+    file.write_all(line.as_bytes())?;
+    Ok(file)
+}
+
+// tombstone ต้องลง disk ก่อนตอบว่าลบแล้ว (ไม่งั้น crash แล้ว document กลับมา)
+fn append_tombstone(json_path: &str, id: u64) -> io::Result<usize> {
+    let line = format!("{}\n", serde_json::json!({ "id": id, TOMBSTONE_KEY: true }));
+    append_line(json_path, &line)?.sync_data()?;
+    Ok(line.len())
+}
+
+// เขียนบรรทัดของ record `id` ใหม่ (เขียนทั้งไฟล์ลง tmp แล้ว rename)
+// คืน false ถ้าไม่มี record นี้ (หรือถูกลบไปแล้ว)
+fn replace(json_path: &str, id: u64, value: &Value) -> io::Result<bool> {
+    let content = match fs::read_to_string(json_path) {
+        Ok(c) => c,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+        Err(e) => return Err(e),
+    };
+
+    // บรรทัดสุดท้ายของ id นี้ที่ไม่ใช่ tombstone (ถ้ามี tombstone ตามหลัง = ถูกลบแล้ว)
+    let mut target: Option<usize> = None;
+    for (i, line) in content.lines().enumerate() {
+        let v = match serde_json::from_str::<Value>(line.trim()) {
+            Ok(v) => v,
+            Err(_) => continue,
+        };
+        if v.get("id").and_then(parse_u64) != Some(id) {
+            continue;
+        }
+        target = if is_tombstone(&v) { None } else { Some(i) };
+    }
+    let target = match target {
+        Some(i) => i,
+        None => return Ok(false),
+    };
+
+    let tmp_path = format!("{}.tmp", json_path);
+    {
+        let f = File::create(&tmp_path)?;
+        let mut w = BufWriter::new(f);
+        for (i, line) in content.lines().enumerate() {
+            if i == target {
+                writeln!(w, "{}", serde_json::to_string(value)?)?;
+            } else {
+                writeln!(w, "{}", line)?;
+            }
+        }
+        w.flush()?;
+        w.get_ref().sync_all()?;
+    }
+    fs::rename(&tmp_path, json_path)?;
+
+    Ok(true)
+}
+
+// เขียน JSONL ใหม่โดยตัด record ที่ถูกลบและบรรทัด tombstone ออก
+fn compact(json_path: &str) -> io::Result<CompactReport> {
+    let content = match fs::read_to_string(json_path) {
+        Ok(c) => c,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(CompactReport::default()),
+        Err(e) => return Err(e),
+    };
+
+    let deleted: HashSet<u64> = content
+        .lines()
+        .filter_map(|line| serde_json::from_str::<Value>(line.trim()).ok())
+        .filter(is_tombstone)
+        .filter_map(|v| v.get("id").and_then(parse_u64))
+        .collect();
+    if deleted.is_empty() {
+        return Ok(CompactReport::default());
+    }
+
+    let tmp_path = format!("{}.tmp", json_path);
+    let mut removed = 0usize;
+    {
+        let f = File::create(&tmp_path)?;
+        let mut w = BufWriter::new(f);
+        for line in content.lines() {
+            let trimmed = line.trim();
+            if trimmed.is_empty() {
+                continue;
+            }
+            // บรรทัดที่ parse ไม่ได้เก็บไว้เหมือนเดิม (load ก็ข้ามอยู่แล้ว)
+            if let Ok(v) = serde_json::from_str::<Value>(trimmed) {
+                if is_tombstone(&v) {
+                    continue;
+                }
+                if v.get("id")
+                    .and_then(parse_u64)
+                    .is_some_and(|id| deleted.contains(&id))
+                {
+                    removed += 1;
+                    continue;
+                }
+            }
+            writeln!(w, "{}", trimmed)?;
+        }
+        w.flush()?;
+        w.get_ref().sync_all()?;
+    }
+
+    let before = content.len() as u64;
+    fs::rename(&tmp_path, json_path)?;
+    let after = fs::metadata(json_path)?.len();
+
+    Ok(CompactReport {
+        records_removed: removed,
+        bytes_reclaimed: before.saturating_sub(after),
+    })
+}
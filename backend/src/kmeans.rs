@@ -0,0 +1,73 @@
+use crate::distance::squared_l2;
+
+/// Lloyd's k-means (squared L2) over row-major `data` of `dim`-sized rows.
+///
+/// Initial centroids are evenly strided rows so training the same data gives
+/// the same centroids. Returns `k * dim` floats; `k` is capped by the row count.
+pub fn train(data: &[f32], dim: usize, k: usize, iterations: usize) -> Vec<f32> {
+    let n = data.len() / dim;
+    let k = k.min(n);
+    if k == 0 {
+        return Vec::new();
+    }
+
+    let mut centroids = Vec::with_capacity(k * dim);
+    for c in 0..k {
+        let row = c * n / k;
+        centroids.extend_from_slice(&data[row * dim..(row + 1) * dim]);
+    }
+
+    let mut assign = vec![usize::MAX; n];
+    for _ in 0..iterations {
+        let mut changed = false;
+        for (row, slot) in assign.iter_mut().enumerate() {
+            let c = nearest(&centroids, dim, &data[row * dim..(row + 1) * dim]);
+            if *slot != c {
+                *slot = c;
+                changed = true;
+            }
+        }
+        if !changed {
+            break;
+        }
+
+        let mut sums = vec![0f32; k * dim];
+        let mut counts = vec![0usize; k];
+        for (row, &c) in assign.iter().enumerate() {
+            counts[c] += 1;
+            let v = &data[row * dim..(row + 1) * dim];
+            for (s, x) in sums[c * dim..(c + 1) * dim].iter_mut().zip(v) {
+                *s += x;
+            }
+        }
+        for c in 0..k {
+            // cluster ว่าง -> ใช้ centroid เดิม
+            if counts[c] == 0 {
+                continue;
+            }
+            let inv = 1.0 / counts[c] as f32;
+            for (dst, s) in centroids[c * dim..(c + 1) * dim]
+                .iter_mut()
+                .zip(&sums[c * dim..(c + 1) * dim])
+            {
+                *dst = s * inv;
+            }
+        }
+    }
+
+    centroids
+}
+
+/// index ของ centroid ที่ใกล้ `v` ที่สุด
+pub fn nearest(centroids: &[f32], dim: usize, v: &[f32]) -> usize {
+    let mut best = 0;
+    let mut best_dist = f32::INFINITY;
+    for (c, centroid) in centroids.chunks_exact(dim).enumerate() {
+        let d = squared_l2(centroid, v);
+        if d < best_dist {
+            best = c;
+            best_dist = d;
+        }
+    }
+    best
+}
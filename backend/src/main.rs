@@ -1,28 +1,54 @@
+mod binary_codes;
+mod collection;
 mod config;
+mod distance;
+mod doc_store;
+mod filter;
 mod flat_index;
 mod handler;
+mod hnsw_index;
+mod ivf_index;
+mod jsonl_store;
+mod kmeans;
 mod model;
+mod paging;
+mod pq_index;
 mod presenter;
-mod utils;
+mod reconcile;
+mod redb_store;
+mod reindex;
+mod schema;
+mod template;
+mod vector_index;
 
-use axum::{routing::post, Router};
+use axum::{
+    routing::{delete, get, post},
+    Router,
+};
 use tokio::net::TcpListener;
 
+use crate::collection::{
+    model_info, CollectionDef, CollectionPaths, Collections, Embedders, DEFAULT_COLLECTION,
+};
 use crate::config::load_config;
-use crate::flat_index::FlatIndex;
-use crate::handler::{create_data, get_data};
+use crate::doc_store::open_document_store;
+use crate::flat_index::{RvixFile, VectorSpec};
+use crate::handler::{
+    compact_data, create_collection, create_data, delete_data, delete_document,
+    drop_collection, get_data, get_document, list_collections, list_data,
+    multi_get_documents, search_batch, train_index, update_data, update_document,
+};
+use crate::model::SearchSettings;
+use crate::reindex::{rebuild, reindex};
+use crate::template::EmbeddingTemplate;
 
 use std::sync::Arc;
-use tokio::sync::Mutex;
 use tower::ServiceBuilder;
 use tower_http::cors::{Any, CorsLayer};
 
-use fastembed::{EmbeddingModel, InitOptions, TextEmbedding};
-
-#[derive(Clone)]
 pub struct AppState {
-    pub embedder: Arc<Mutex<TextEmbedding>>,
-    pub index: Arc<Mutex<FlatIndex>>,
+    pub collections: Collections,
+    pub search: SearchSettings,
 }
 
 #[tokio::main]
@@ -30,20 +56,125 @@ async fn main() {
     // ---- config ----
     let config = load_config();
     let addr = format!("{}:{}", config.app.url, config.app.port);
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    // collection ของคำสั่ง (ไม่ระบุ = reviews)
+    let target = args
+        .iter()
+        .skip(1)
+        .find(|a| !a.starts_with("--"))
+        .map_or(DEFAULT_COLLECTION, String::as_str);
+
+    // ---- `backend verify-index [collection] [--repair]`: ตรวจ/ซ่อม index แล้วจบ ----
+    if args.first().map(String::as_str) == Some("verify-index") {
+        let repair = args.iter().any(|a| a == "--repair");
+        let def = CollectionDef::load(target, &config.embedding, &config.index)
+            .expect("unknown collection");
+        // dim ตาม embedding model ของ collection (บันทึกใน header ของ index)
+        let (_, dim, _) = model_info(&def.embedding.model).expect("unknown embedding model");
+        let report = RvixFile::verify(&CollectionPaths::of(target).index, dim, repair)
+            .expect("failed to verify vector index");
+        println!("{}", serde_json::to_string_pretty(&report).unwrap());
+        if !report.ok && !report.repaired {
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    // ---- search pool (rayon) ----
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(config.index.threads)
+        .build_global()
+        .expect("failed to init search thread pool");
 
-    // ---- init embedder (fastembed) ----
-    let mut opts = InitOptions::default();
-    opts.model_name = EmbeddingModel::AllMiniLML6V2;
+    // ---- `backend reindex [collection] --rebuild`: embed ทุก document ลง index ใหม่ ----
+    // ทำก่อนเปิด collection: index เดิมอาจเปิดไม่ได้แล้ว (model/dim/metric เปลี่ยน)
+    let reindexing = args.first().map(String::as_str) == Some("reindex");
+    let rebuilt = if reindexing && args.iter().any(|a| a == "--rebuild") {
+        let def = CollectionDef::load(target, &config.embedding, &config.index)
+            .expect("unknown collection");
+        let paths = CollectionPaths::of(target);
+        let template = EmbeddingTemplate::parse(&def.embedding.template, &def.schema)
+            .expect("invalid embedding template");
+        let (model, dim, model_code) =
+            model_info(&def.embedding.model).expect("unknown embedding model");
+        let documents = open_document_store(
+            &paths.jsonl,
+            &paths.redb,
+            &config.documents,
+            &def.schema,
+        )
+        .expect("failed to open documents");
+        let embedder = Embedders::default()
+            .get(&model)
+            .expect("failed to load embedding model");
+        let spec = VectorSpec {
+            dim,
+            precision: def.index.precision,
+            metric: def.index.metric,
+            model: model_code,
+            template: template.version(),
+        };
+        let report = rebuild(
+            &mut *embedder.lock().await,
+            &paths.index,
+            &spec,
+            documents.as_ref(),
+            &template,
+        )
+        .expect("failed to rebuild vector index");
+        Some(report)
+    } else {
+        None
+    };
 
-    let embedder = TextEmbedding::try_new(opts).expect("failed to init TextEmbedding (fastembed)");
+    // ---- collections: reviews + src/data/collections/* (index + document store + embedder) ----
+    let mut collections = Collections::new(config.index, config.documents);
+    collections
+        .open_all(&config.embedding)
+        .expect("failed to open collections");
 
-    let dim = 384usize;
-    let index = FlatIndex::open_or_create("src/data/reviews.index", dim)
-        .expect("failed to open/create FlatIndex");
+    // ---- `backend reindex [collection]`: embed ทุก document ใหม่ด้วย template ปัจจุบันแล้วจบ ----
+    // (--rebuild เขียน index ใหม่ไปแล้วด้านบน เปิด collection เพื่อ convert/build graph ให้ครบ)
+    if reindexing {
+        let report = match rebuilt {
+            Some(report) => report,
+            None => {
+                let collection = collections.get(target).await.expect("unknown collection");
+                let mut embedder = collection.embedder.lock().await;
+                let mut index = collection.index.write().await;
+                let documents = collection.documents.read().await;
+                reindex(
+                    &mut embedder,
+                    index.as_mut(),
+                    documents.as_ref(),
+                    &collection.template,
+                )
+                .expect("failed to reindex documents")
+            }
+        };
+        println!("{}", serde_json::to_string_pretty(&report).unwrap());
+        return;
+    }
+    let mut stale = false;
+    for collection in collections.list().await {
+        if collection.needs_reindex().await {
+            eprintln!(
+                "{} was embedded with another embedding template than `{}`; \
+                 run `backend reindex {}` to re-embed its documents",
+                collection.paths.index,
+                collection.template.text().escape_debug(),
+                collection.name
+            );
+            stale = true;
+        }
+    }
+    if stale {
+        std::process::exit(1);
+    }
 
     let state = Arc::new(AppState {
-        embedder: Arc::new(Mutex::new(embedder)),
-        index: Arc::new(Mutex::new(index)),
+        collections,
+        search: config.search,
     });
 
     // ---- cors + middleware ----
@@ -57,6 +188,30 @@ async fn main() {
     let app = Router::new()
         .route("/create-data", post(create_data))
         .route("/get-data", post(get_data))
+        .route("/list-data", post(list_data))
+        .route("/reviews/{id}", get(get_document))
+        .route("/reviews/multi-get", post(multi_get_documents))
+        .route("/search-batch", post(search_batch))
+        .route("/train-index", post(train_index))
+        .route("/update-data", post(update_data))
+        .route("/delete-data", post(delete_data))
+        .route("/compact", post(compact_data))
+        // ---- collections: route เดียวกับด้านบนแต่เลือก collection จาก path ----
+        .route("/collections", get(list_collections).post(create_collection))
+        .route("/collections/{name}", delete(drop_collection))
+        .route("/collections/{name}/search", post(get_data))
+        .route("/collections/{name}/search-batch", post(search_batch))
+        .route("/collections/{name}/list", post(list_data))
+        .route("/collections/{name}/documents", post(create_data))
+        .route("/collections/{name}/documents/multi-get", post(multi_get_documents))
+        .route(
+            "/collections/{name}/documents/{id}",
+            get(get_document)
+                .patch(update_document)
+                .delete(delete_document),
+        )
+        .route("/collections/{name}/train-index", post(train_index))
+        .route("/collections/{name}/compact", post(compact_data))
         .with_state(state)
         .layer(middleware_stack);
 
@@ -1,8 +1,23 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::distance::Metric;
+use crate::filter::Filter;
+use crate::flat_index::Precision;
+use crate::template::LEGACY_TEMPLATE;
 
 #[derive(Debug, Deserialize)]
 pub struct AppConfig {
     pub app: AppSettings,
+    #[serde(default)]
+    pub index: IndexSettings,
+    #[serde(default)]
+    pub search: SearchSettings,
+    #[serde(default)]
+    pub documents: DocumentSettings,
+    // ของ collection reviews (collection อื่นกำหนดตอนสร้างผ่าน POST /collections)
+    #[serde(default)]
+    pub embedding: EmbeddingSettings,
 }
 
 #[derive(Debug, Deserialize)]
@@ -11,8 +26,335 @@ pub struct AppSettings {
     pub port: u16,
 }
 
+/// Server-side limits of search requests.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(default)]
+pub struct SearchSettings {
+    pub max_top_k: usize,
+    pub max_offset: usize,
+    // ค่าสูงสุดของ `nprobe` / `rerank` ที่ client ส่งมาได้
+    pub max_nprobe: usize,
+    pub max_rerank: usize,
+    // จำนวน hit สูงสุดของ radius search (default ของ `max_results` ด้วย)
+    pub max_radius_results: usize,
+}
+
+impl Default for SearchSettings {
+    fn default() -> Self {
+        Self {
+            max_top_k: 100,
+            max_offset: 10_000,
+            max_nprobe: 1024,
+            max_rerank: 10_000,
+            max_radius_results: 1000,
+        }
+    }
+}
+
+impl SearchSettings {
+    /// ตรวจ `nprobe` / `rerank` ของ request (ค่าที่ใหญ่เกินทำให้จอง memory ตามไม่ไหว)
+    pub fn check_tuning(&self, nprobe: Option<usize>, rerank: Option<usize>) -> Result<(), String> {
+        if nprobe.is_some_and(|n| n == 0 || n > self.max_nprobe) {
+            return Err(format!("nprobe must be between 1 and {}", self.max_nprobe));
+        }
+        if rerank.is_some_and(|n| n > self.max_rerank) {
+            return Err(format!("rerank must be at most {}", self.max_rerank));
+        }
+        Ok(())
+    }
+
+    /// จำนวน hit สูงสุดของ radius search (ไม่ส่ง `max_results` มา = ค่าสูงสุดจาก config)
+    pub fn radius_cap(&self, max_results: Option<usize>) -> Result<usize, String> {
+        match max_results {
+            None => Ok(self.max_radius_results),
+            Some(n) if n == 0 || n > self.max_radius_results => Err(format!(
+                "max_results must be between 1 and {}",
+                self.max_radius_results
+            )),
+            Some(n) => Ok(n),
+        }
+    }
+}
+
+/// Where the metadata of every collection is stored.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct DocumentSettings {
+    #[serde(default)]
+    pub store: StoreKind,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StoreKind {
+    #[default]
+    Jsonl,
+    Redb,
+}
+
+/// Which model embeds a collection and what text of a document it embeds.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct EmbeddingSettings {
+    // ชื่อ model ของ fastembed (เช่น `AllMiniLML6V2`) บันทึกใน header ของ index
+    pub model: String,
+    // `{field}` = ค่าของ field ใน schema, เปลี่ยนแล้วต้อง `backend reindex`
+    pub template: String,
+}
+
+impl Default for EmbeddingSettings {
+    fn default() -> Self {
+        Self {
+            model: "AllMiniLML6V2".to_string(),
+            template: LEGACY_TEMPLATE.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IndexKind {
+    #[default]
+    Flat,
+    Hnsw,
+    Ivf,
+    Pq,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct IndexSettings {
+    #[serde(default)]
+    pub kind: IndexKind,
+    #[serde(default)]
+    pub precision: Precision,
+    // เลือกได้ตอนสร้าง index เท่านั้น (บันทึกไว้ใน header)
+    #[serde(default)]
+    pub metric: Metric,
+    #[serde(default)]
+    pub binary: BinarySettings,
+    #[serde(default)]
+    pub hnsw: HnswSettings,
+    #[serde(default)]
+    pub ivf: IvfSettings,
+    #[serde(default)]
+    pub pq: PqSettings,
+    // จำนวน thread ของ search pool (0 = เท่าจำนวน CPU)
+    #[serde(default)]
+    pub threads: usize,
+}
+
+/// Vector index of one collection, chosen when it is created and kept in
+/// `<name>.index.json`. The tuning of each kind (binary, hnsw, ivf, pq) is
+/// shared by every collection and comes from `index` in config.yml.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+pub struct IndexDef {
+    pub kind: IndexKind,
+    // เปลี่ยนใน `<name>.index.json` แล้ว start ใหม่ -> convert ไฟล์เดิมให้ (เหมือน config.yml)
+    pub precision: Precision,
+    // บันทึกใน header ของ index เปลี่ยนทีหลังต้อง `backend reindex <name> --rebuild`
+    pub metric: Metric,
+}
+
+impl IndexDef {
+    /// ค่าจาก config.yml (reviews และ collection ที่ไม่มี `<name>.index.json`)
+    pub fn from_settings(settings: &IndexSettings) -> Self {
+        Self {
+            kind: settings.kind,
+            precision: settings.precision,
+            metric: settings.metric,
+        }
+    }
+
+    /// settings ที่ใช้เปิด index ของ collection: kind/precision/metric ของ collection
+    /// ส่วนค่าอื่นจาก config.yml
+    pub fn settings(&self, global: &IndexSettings) -> IndexSettings {
+        IndexSettings {
+            kind: self.kind,
+            precision: self.precision,
+            metric: self.metric,
+            ..global.clone()
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct BinarySettings {
+    pub enabled: bool,
+    pub rescore: usize,
+}
+
+impl Default for BinarySettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            rescore: 200,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct HnswSettings {
+    pub m: usize,
+    pub ef_construction: usize,
+    pub ef_search: usize,
+    pub save_every: usize,
+}
+
+impl Default for HnswSettings {
+    fn default() -> Self {
+        Self {
+            m: 16,
+            ef_construction: 200,
+            ef_search: 64,
+            save_every: 100,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct IvfSettings {
+    pub nlist: usize,
+    pub nprobe: usize,
+    pub iterations: usize,
+    pub train_sample: usize,
+    pub save_every: usize,
+}
+
+impl Default for IvfSettings {
+    fn default() -> Self {
+        Self {
+            nlist: 256,
+            nprobe: 8,
+            iterations: 20,
+            train_sample: 65536,
+            save_every: 100,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct PqSettings {
+    pub m: usize,
+    pub iterations: usize,
+    pub train_sample: usize,
+    pub rerank: usize,
+}
+
+impl Default for PqSettings {
+    fn default() -> Self {
+        Self {
+            m: 48,
+            iterations: 20,
+            train_sample: 65536,
+            rerank: 100,
+        }
+    }
+}
+
 #[derive(Debug, Deserialize)]
 pub struct SearchRequest {
     pub(crate) query: String,
-    // pub top_k: Option<usize>,
+    // จำนวนผลต่อหน้า (default 10, radius search = search.max_top_k) สูงสุดตาม search.max_top_k
+    pub(crate) top_k: Option<usize>,
+    // ข้ามผล `offset` ตัวแรก หรือใช้ `cursor` (next_cursor จากหน้าก่อน) อย่างใดอย่างหนึ่ง
+    pub(crate) offset: Option<usize>,
+    pub(crate) cursor: Option<String>,
+    pub(crate) nprobe: Option<usize>,
+    pub(crate) rerank: Option<usize>,
+    // ทุก filter ต้องผ่าน (AND)
+    #[serde(default)]
+    pub(crate) filters: Vec<Filter>,
+    // radius search: คืนทุก record ในรัศมีแทน top-k (ใช้ได้อย่างใดอย่างหนึ่ง)
+    pub(crate) max_distance: Option<f32>,
+    pub(crate) min_similarity: Option<f32>,
+    // จำนวนผลสูงสุดของ radius search (None = search.max_radius_results)
+    pub(crate) max_results: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ListRequest {
+    // field ที่ใช้เรียง (default id) ต้องเป็น field ใน schema
+    pub(crate) sort_by: Option<String>,
+    #[serde(default)]
+    pub(crate) order: SortOrder,
+    // จำนวน document ต่อหน้า (default 10) สูงสุดตาม search.max_top_k
+    pub(crate) limit: Option<usize>,
+    // next_cursor จากหน้าก่อน
+    pub(crate) cursor: Option<String>,
+    #[serde(default)]
+    pub(crate) filters: Vec<Filter>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    #[default]
+    Asc,
+    Desc,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GetDocumentQuery {
+    // แนบ vector ที่เก็บใน index ของ collection มาใน field embedding
+    #[serde(default)]
+    pub(crate) include_vector: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MultiGetRequest {
+    pub(crate) ids: Vec<u64>,
+    #[serde(default)]
+    pub(crate) include_vector: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DocumentPath {
+    pub(crate) id: u64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeleteRequest {
+    pub(crate) id: u64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SearchBatchRequest {
+    pub(crate) queries: Vec<String>,
+    pub(crate) top_k: Option<usize>,
+    pub(crate) nprobe: Option<usize>,
+    pub(crate) rerank: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateCollectionRequest {
+    // ใช้เป็นชื่อ directory: a-z 0-9 _ - ไม่เกิน 64 ตัว
+    pub(crate) name: String,
+    // รูปแบบเดียวกับ reviews.schema.json
+    pub(crate) schema: Value,
+    #[serde(default)]
+    pub(crate) embedding: EmbeddingSettings,
+    #[serde(default)]
+    pub(crate) index: IndexRequest,
+}
+
+/// `index` of `POST /collections`; what isn't sent comes from config.yml.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct IndexRequest {
+    pub(crate) kind: Option<IndexKind>,
+    pub(crate) precision: Option<Precision>,
+    pub(crate) metric: Option<Metric>,
+}
+
+impl IndexRequest {
+    pub fn resolve(&self, defaults: &IndexSettings) -> IndexDef {
+        IndexDef {
+            kind: self.kind.unwrap_or(defaults.kind),
+            precision: self.precision.unwrap_or(defaults.precision),
+            metric: self.metric.unwrap_or(defaults.metric),
+        }
+    }
 }
@@ -0,0 +1,162 @@
+use serde_json::Value;
+use std::cmp::Ordering;
+
+use crate::model::SearchSettings;
+
+/// Validated result window of a search request: skip `offset` hits, then
+/// return `top_k` of them (`None` = everything that is left).
+#[derive(Debug, Clone, Copy)]
+pub struct Page {
+    pub offset: usize,
+    pub top_k: Option<usize>,
+}
+
+impl Page {
+    /// ตรวจ top_k / offset / cursor ของ request
+    ///
+    /// `fingerprint` ระบุ query + filter ของ request; cursor ที่ออกให้ query อื่นจะใช้ไม่ได้
+    pub fn resolve(
+        top_k: Option<usize>,
+        offset: Option<usize>,
+        cursor: Option<&str>,
+        default_top_k: Option<usize>,
+        fingerprint: u32,
+        limits: &SearchSettings,
+    ) -> Result<Self, String> {
+        let top_k = top_k.or(default_top_k);
+        if let Some(k) = top_k {
+            if k == 0 || k > limits.max_top_k {
+                return Err(format!("top_k must be between 1 and {}", limits.max_top_k));
+            }
+        }
+
+        let offset = match (offset, cursor) {
+            (Some(_), Some(_)) => return Err("use either offset or cursor, not both".to_string()),
+            (Some(offset), None) => offset,
+            (None, Some(cursor)) => decode_cursor(cursor, fingerprint)?,
+            (None, None) => 0,
+        };
+        if offset > limits.max_offset {
+            return Err(format!("offset must be at most {}", limits.max_offset));
+        }
+
+        Ok(Self { offset, top_k })
+    }
+
+    /// จำนวน hit ที่ต้องขอจาก index (offset + top_k)
+    pub fn window(&self) -> Option<usize> {
+        self.top_k.map(|k| self.offset + k)
+    }
+
+    /// ตัด hit ให้เหลือเฉพาะหน้านี้
+    pub fn apply<T>(&self, hits: Vec<T>) -> Vec<T> {
+        let page = hits.into_iter().skip(self.offset);
+        match self.top_k {
+            Some(k) => page.take(k).collect(),
+            None => page.collect(),
+        }
+    }
+
+    /// cursor ของหน้าถัดไป (None = หน้านี้เป็นหน้าสุดท้าย)
+    pub fn next_cursor(&self, total: usize, fingerprint: u32) -> Option<String> {
+        let next = self.window()?;
+        (next < total).then(|| encode_cursor(next, fingerprint))
+    }
+}
+
+/// crc ของส่วนที่กำหนดผลลัพธ์ของ request (ใช้ผูก cursor กับ query)
+pub fn fingerprint(parts: &str) -> u32 {
+    crc32fast::hash(parts.as_bytes())
+}
+
+// cursor = hex ของ offset (u64 LE) + fingerprint (u32 LE)
+fn encode_cursor(offset: usize, fingerprint: u32) -> String {
+    let mut bytes = (offset as u64).to_le_bytes().to_vec();
+    bytes.extend_from_slice(&fingerprint.to_le_bytes());
+    to_hex(&bytes)
+}
+
+fn decode_cursor(cursor: &str, fingerprint: u32) -> Result<usize, String> {
+    let invalid = || "invalid cursor".to_string();
+    let bytes = from_hex(cursor).ok_or_else(invalid)?;
+    if bytes.len() != 12 {
+        return Err(invalid());
+    }
+
+    let offset = u64::from_le_bytes(bytes[..8].try_into().unwrap());
+    let owner = u32::from_le_bytes(bytes[8..].try_into().unwrap());
+    if owner != fingerprint {
+        return Err("cursor does not belong to this query".to_string());
+    }
+    usize::try_from(offset).map_err(|_| invalid())
+}
+
+/// Keyset position of the list endpoint: sort value + id of the last document
+/// on the previous page. Documents inserted or deleted meanwhile don't shift
+/// the next page the way an offset would.
+#[derive(Debug, Clone)]
+pub struct ListCursor {
+    pub value: Value,
+    pub id: u64,
+}
+
+impl ListCursor {
+    // cursor = hex ของ JSON [value, id, fingerprint]
+    pub fn encode(&self, fingerprint: u32) -> String {
+        let key = serde_json::json!([self.value, self.id, fingerprint]);
+        to_hex(key.to_string().as_bytes())
+    }
+
+    pub fn decode(cursor: &str, fingerprint: u32) -> Result<Self, String> {
+        let invalid = || "invalid cursor".to_string();
+        let bytes = from_hex(cursor).ok_or_else(invalid)?;
+        let key: (Value, u64, u32) = serde_json::from_slice(&bytes).map_err(|_| invalid())?;
+        if key.2 != fingerprint {
+            return Err("cursor does not belong to this query".to_string());
+        }
+        Ok(Self {
+            value: key.0,
+            id: key.1,
+        })
+    }
+}
+
+/// ลำดับรวมของค่า JSON ใช้ sort document:
+/// ไม่มีค่า/null < bool < ตัวเลข < string < array < object
+pub fn compare_values(a: &Value, b: &Value) -> Ordering {
+    fn rank(v: &Value) -> u8 {
+        match v {
+            Value::Null => 0,
+            Value::Bool(_) => 1,
+            Value::Number(_) => 2,
+            Value::String(_) => 3,
+            Value::Array(_) => 4,
+            Value::Object(_) => 5,
+        }
+    }
+    match (a, b) {
+        (Value::Bool(x), Value::Bool(y)) => x.cmp(y),
+        (Value::Number(x), Value::Number(y)) => {
+            let x = x.as_f64().unwrap_or(f64::NAN);
+            let y = y.as_f64().unwrap_or(f64::NAN);
+            x.total_cmp(&y)
+        }
+        (Value::String(x), Value::String(y)) => x.cmp(y),
+        _ if rank(a) == rank(b) => a.to_string().cmp(&b.to_string()),
+        _ => rank(a).cmp(&rank(b)),
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) || !s.is_ascii() {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&s[i..i + 2], 16).ok())
+        .collect()
+}
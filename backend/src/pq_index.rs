@@ -0,0 +1,591 @@
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use crate::distance::{dot, l1_distance, normalized, sort_hits, squared_l2, Metric, TopK};
+use crate::flat_index::{RvixFile, VectorSpec};
+use crate::kmeans;
+use crate::model::PqSettings;
+use crate::vector_index::{CompactReport, SearchParams, TrainReport, VectorIndex};
+
+const MAGIC: &[u8; 4] = b"RVPQ";
+const VERSION: u32 = 1;
+const KSUB: usize = 256;
+
+/// Product-quantized index over the vectors of an RVIX file.
+///
+/// Only the ids and the `m`-byte codes are kept in memory. `reviews.index.pq`
+/// holds the codebooks followed by one `(id, code)` record per RVIX record;
+/// the full-precision vectors stay in `reviews.index` and are read back only
+/// to re-rank the best candidates. Until `train` has been run every search
+/// falls back to the exact flat scan.
+pub struct PqIndex {
+    store: RvixFile,
+    pq_path: String,
+    m: usize,
+    iterations: usize,
+    train_sample: usize,
+    rerank: usize,
+
+    // node (= slot ใน RVIX) -> record id / code
+    ids: Vec<u64>,
+    codes: Vec<u8>,
+    // m * KSUB * dsub, ว่าง = ยังไม่ train
+    codebooks: Vec<f32>,
+}
+
+impl PqIndex {
+    pub fn open_or_create(
+        index_path: impl Into<String>,
+        spec: &VectorSpec,
+        settings: &PqSettings,
+    ) -> io::Result<Self> {
+        if settings.m == 0 || !spec.dim.is_multiple_of(settings.m) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "pq.m must divide the vector dim",
+            ));
+        }
+
+        let index_path = index_path.into();
+        let pq_path = format!("{}.pq", index_path);
+        let store = RvixFile::open_or_create(index_path, spec)?;
+
+        let mut index = Self {
+            store,
+            pq_path,
+            m: settings.m,
+            iterations: settings.iterations,
+            train_sample: settings.train_sample.max(1),
+            rerank: settings.rerank,
+            ids: Vec::new(),
+            codes: Vec::new(),
+            codebooks: Vec::new(),
+        };
+
+        if Path::new(&index.pq_path).exists() {
+            index.load()?;
+        } else {
+            index.store.for_each(|id, _| index.ids.push(id))?;
+        }
+
+        Ok(index)
+    }
+
+    pub fn dim(&self) -> usize {
+        self.store.dim()
+    }
+
+    pub fn metric(&self) -> Metric {
+        self.store.metric()
+    }
+
+    pub fn template(&self) -> u32 {
+        self.store.template()
+    }
+
+    pub fn set_template(&mut self, template: u32) -> io::Result<()> {
+        self.store.set_template(template)
+    }
+
+    pub fn is_trained(&self) -> bool {
+        !self.codebooks.is_empty()
+    }
+
+    pub fn append(&mut self, vec: &[f32]) -> io::Result<u64> {
+        let id = self.store.append(vec)?;
+        self.ids.push(id);
+
+        if self.is_trained() {
+            let code = self.encode(vec);
+            self.codes.extend_from_slice(&code);
+            self.append_codes(&[id], &code)?;
+        }
+
+        Ok(id)
+    }
+
+    pub fn search(
+        &self,
+        query: &[f32],
+        top_k: usize,
+        params: &SearchParams,
+    ) -> io::Result<Vec<(u64, f32)>> {
+        if query.len() != self.dim() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "dimension mismatch",
+            ));
+        }
+        if top_k == 0 {
+            return Ok(vec![]);
+        }
+        let metric = self.store.metric();
+        if !self.is_trained() {
+            // ยังไม่ train -> scan vector เต็มจาก RVIX
+            let mut best = TopK::new(top_k);
+            self.store.for_each(|id, vec| {
+                if !self.store.is_deleted(id) && params.allows(id) {
+                    best.push(id, metric.distance(query, vec));
+                }
+            })?;
+            return Ok(best.into_sorted_vec());
+        }
+
+        // distance table: ระยะจาก sub-query ถึงทุก centroid ของแต่ละ subspace
+        let q = self.stored_form(query);
+        let dsub = self.dim() / self.m;
+        let mut table = vec![0f32; self.m * KSUB];
+        for j in 0..self.m {
+            let sub_q = &q[j * dsub..(j + 1) * dsub];
+            for c in 0..KSUB {
+                let centroid = self.centroid(j, c);
+                table[j * KSUB + c] = match metric {
+                    Metric::Cosine | Metric::L2 => squared_l2(sub_q, centroid),
+                    Metric::Dot => -dot(sub_q, centroid),
+                    Metric::L1 => l1_distance(sub_q, centroid),
+                };
+            }
+        }
+
+        // ADC: ผลรวมระยะของแต่ละ subspace
+        // cosine: |q - x|^2 ของ vector ที่ normalize แล้ว = 2 * cosine distance
+        let adc_scale = if metric == Metric::Cosine { 0.5 } else { 1.0 };
+        let rerank = params.rerank.unwrap_or(self.rerank);
+        let mut shortlist = TopK::new(if rerank == 0 {
+            top_k
+        } else {
+            rerank.max(top_k)
+        });
+        for (node, code) in self.codes.chunks_exact(self.m).enumerate() {
+            let id = self.ids[node];
+            if self.store.is_deleted(id) || !params.allows(id) {
+                continue;
+            }
+            let d: f32 = code
+                .iter()
+                .enumerate()
+                .map(|(j, &c)| table[j * KSUB + c as usize])
+                .sum();
+            shortlist.push(node as u64, d * adc_scale);
+        }
+        let scored = shortlist.into_sorted_vec();
+
+        if rerank == 0 {
+            return Ok(scored
+                .into_iter()
+                .map(|(node, d)| (self.ids[node as usize], d))
+                .collect());
+        }
+
+        // re-rank ด้วย vector เต็มจาก RVIX
+        let slots: Vec<usize> = scored.iter().map(|&(node, _)| node as usize).collect();
+        let mut exact = TopK::new(top_k);
+        for (id, vec) in self.store.read_slots(&slots)? {
+            exact.push(id, metric.distance(query, &vec));
+        }
+        Ok(exact.into_sorted_vec())
+    }
+
+    /// radius search แบบ exact: scan vector เต็มจาก RVIX (code PQ ประมาณ distance ไม่พอจะตัดด้วยรัศมี)
+    pub fn search_range(
+        &self,
+        query: &[f32],
+        max_distance: f32,
+        cap: Option<usize>,
+        params: &SearchParams,
+    ) -> io::Result<Vec<(u64, f32)>> {
+        if query.len() != self.dim() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "dimension mismatch",
+            ));
+        }
+        let metric = self.store.metric();
+        let mut hits = Vec::new();
+        self.store.for_each(|id, vec| {
+            if self.store.is_deleted(id) || !params.allows(id) {
+                return;
+            }
+            let d = metric.distance(query, vec);
+            if d <= max_distance {
+                hits.push((id, d));
+            }
+        })?;
+        sort_hits(&mut hits);
+        if let Some(cap) = cap {
+            hits.truncate(cap);
+        }
+        Ok(hits)
+    }
+
+    pub fn ids(&self) -> Vec<u64> {
+        self.ids
+            .iter()
+            .copied()
+            .filter(|&id| !self.store.is_deleted(id))
+            .collect()
+    }
+
+    pub fn is_deleted(&self, id: u64) -> bool {
+        self.store.is_deleted(id)
+    }
+
+    /// vector เต็มจาก RVIX ของแต่ละ id (None = ไม่มี/ถูกลบแล้ว)
+    pub fn vectors(&self, ids: &[u64]) -> io::Result<Vec<Option<Vec<f32>>>> {
+        let slots: Vec<Option<usize>> = ids
+            .iter()
+            .map(|&id| match self.ids.binary_search(&id) {
+                Ok(node) if !self.store.is_deleted(id) => Some(node),
+                _ => None,
+            })
+            .collect();
+        self.store.read_vectors(&slots)
+    }
+
+    pub fn delete(&mut self, id: u64) -> io::Result<bool> {
+        if self.ids.binary_search(&id).is_err() || self.store.is_deleted(id) {
+            return Ok(false);
+        }
+        self.store.mark_deleted(id)?;
+        Ok(true)
+    }
+
+    /// แทน vector ของ `id` แล้ว encode code ใหม่ทับของเดิมใน .pq
+    pub fn replace(&mut self, id: u64, vec: &[f32]) -> io::Result<bool> {
+        let node = match self.ids.binary_search(&id) {
+            Ok(node) if !self.store.is_deleted(id) => node,
+            _ => return Ok(false),
+        };
+        self.store.write_slot(node, id, vec)?;
+
+        if self.is_trained() {
+            let code = self.encode(vec);
+            self.codes[node * self.m..(node + 1) * self.m].copy_from_slice(&code);
+
+            let header_len = 16 + self.codebooks.len() as u64 * 4;
+            let mut f = OpenOptions::new().write(true).open(&self.pq_path)?;
+            // ข้าม id ของ record
+            f.seek(SeekFrom::Start(
+                header_len + node as u64 * (8 + self.m as u64) + 8,
+            ))?;
+            f.write_all(&code)?;
+            f.sync_data()?;
+        }
+
+        Ok(true)
+    }
+
+    /// compact RVIX แล้วตัด code ของ record ที่ถูกลบออกจาก .pq ด้วย
+    pub fn compact(&mut self) -> io::Result<CompactReport> {
+        let live: Vec<bool> = self
+            .ids
+            .iter()
+            .map(|&id| !self.store.is_deleted(id))
+            .collect();
+        let report = self.store.compact()?;
+        if report.records_removed == 0 {
+            return Ok(report);
+        }
+
+        let mut ids = Vec::with_capacity(self.ids.len());
+        let mut codes = Vec::with_capacity(self.codes.len());
+        for (node, &id) in self.ids.iter().enumerate() {
+            if !live[node] {
+                continue;
+            }
+            ids.push(id);
+            if self.is_trained() {
+                codes.extend_from_slice(&self.codes[node * self.m..(node + 1) * self.m]);
+            }
+        }
+        self.ids = ids;
+        self.codes = codes;
+
+        if self.is_trained() {
+            self.write_all()?;
+        }
+        Ok(report)
+    }
+
+    /// train codebooks ใหม่ แล้ว encode ทุก record ใหม่
+    pub fn train(&mut self) -> io::Result<TrainReport> {
+        let dim = self.dim();
+        let n = self.ids.len();
+        if n == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "cannot train an empty index",
+            ));
+        }
+
+        // sample แบบเว้นระยะเท่าๆ กัน
+        let sample_n = n.min(self.train_sample);
+        let mut sample = Vec::with_capacity(sample_n * dim);
+        let mut next = 0usize;
+        let mut node = 0usize;
+        self.store.for_each(|_, vec| {
+            if next < sample_n && node == next * n / sample_n {
+                sample.extend(self.stored_form(vec));
+                next += 1;
+            }
+            node += 1;
+        })?;
+
+        let dsub = dim / self.m;
+        let mut codebooks = Vec::with_capacity(self.m * KSUB * dsub);
+        for j in 0..self.m {
+            let sub: Vec<f32> = sample
+                .chunks_exact(dim)
+                .flat_map(|v| v[j * dsub..(j + 1) * dsub].iter().copied())
+                .collect();
+            let mut book = kmeans::train(&sub, dsub, KSUB, self.iterations);
+            // sample น้อยกว่า KSUB -> เติม centroid ซ้ำให้ครบ (code ยังเป็น u8 ได้)
+            while book.len() < KSUB * dsub {
+                let row = book[..dsub].to_vec();
+                book.extend(row);
+            }
+            codebooks.extend(book);
+        }
+        self.codebooks = codebooks;
+
+        self.encode_all()?;
+        self.write_all()?;
+
+        Ok(TrainReport {
+            records: n,
+            centroids: self.m * KSUB,
+        })
+    }
+
+    fn centroid(&self, sub: usize, c: usize) -> &[f32] {
+        let dsub = self.dim() / self.m;
+        let start = (sub * KSUB + c) * dsub;
+        &self.codebooks[start..start + dsub]
+    }
+
+    /// vector ในรูปที่ใช้ train/encode (cosine = normalize)
+    fn stored_form(&self, vec: &[f32]) -> Vec<f32> {
+        if self.store.metric().normalizes() {
+            normalized(vec)
+        } else {
+            vec.to_vec()
+        }
+    }
+
+    fn encode(&self, vec: &[f32]) -> Vec<u8> {
+        let v = self.stored_form(vec);
+        let dsub = self.dim() / self.m;
+        (0..self.m)
+            .map(|j| {
+                let book = &self.codebooks[j * KSUB * dsub..(j + 1) * KSUB * dsub];
+                kmeans::nearest(book, dsub, &v[j * dsub..(j + 1) * dsub]) as u8
+            })
+            .collect()
+    }
+
+    fn encode_all(&mut self) -> io::Result<()> {
+        let mut ids = Vec::new();
+        let mut codes = Vec::new();
+        self.store.for_each(|id, vec| {
+            ids.push(id);
+            codes.extend(self.encode(vec));
+        })?;
+        self.ids = ids;
+        self.codes = codes;
+        Ok(())
+    }
+
+    fn load(&mut self) -> io::Result<()> {
+        let dim = self.dim();
+        let f = File::open(&self.pq_path)?;
+        let mut r = BufReader::new(f);
+
+        let mut magic = [0u8; 4];
+        r.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "bad pq magic"));
+        }
+        if r.read_u32::<LittleEndian>()? != VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "unsupported pq version",
+            ));
+        }
+        let file_dim = r.read_u32::<LittleEndian>()? as usize;
+        let file_m = r.read_u32::<LittleEndian>()? as usize;
+        if file_dim != dim || file_m != self.m {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "dim/m mismatch with existing pq file",
+            ));
+        }
+        let mut codebooks = vec![0f32; self.m * KSUB * (dim / self.m)];
+        r.read_f32_into::<LittleEndian>(&mut codebooks)?;
+        self.codebooks = codebooks;
+
+        let mut code_ids = Vec::new();
+        let mut codes = Vec::new();
+        let mut code = vec![0u8; self.m];
+        let mut complete = true;
+        loop {
+            let id = match r.read_u64::<LittleEndian>() {
+                Ok(v) => v,
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+                Err(e) => return Err(e),
+            };
+            if let Err(e) = r.read_exact(&mut code) {
+                if e.kind() != io::ErrorKind::UnexpectedEof {
+                    return Err(e);
+                }
+                complete = false;
+                break;
+            }
+            code_ids.push(id);
+            codes.extend_from_slice(&code);
+        }
+
+        // code ต้องตรงกับลำดับ record ใน RVIX, record ที่ยังไม่มี code -> encode เพิ่ม
+        let mut ids = Vec::new();
+        let mut missing_ids = Vec::new();
+        let mut missing_codes = Vec::new();
+        self.store.for_each(|id, vec| {
+            let node = ids.len();
+            if node < code_ids.len() {
+                if code_ids[node] != id {
+                    complete = false;
+                }
+            } else {
+                missing_ids.push(id);
+                missing_codes.extend(self.encode(vec));
+            }
+            ids.push(id);
+        })?;
+        if code_ids.len() > ids.len() {
+            complete = false;
+        }
+
+        if !complete {
+            self.encode_all()?;
+            return self.write_all();
+        }
+
+        self.ids = ids;
+        self.codes = codes;
+        self.codes.extend_from_slice(&missing_codes);
+        if !missing_ids.is_empty() {
+            self.append_codes(&missing_ids, &missing_codes)?;
+        }
+
+        Ok(())
+    }
+
+    /// เขียนไฟล์ .pq ใหม่ทั้งไฟล์ (tmp แล้ว rename)
+    fn write_all(&self) -> io::Result<()> {
+        let tmp_path = format!("{}.tmp", self.pq_path);
+        {
+            let f = File::create(&tmp_path)?;
+            let mut w = BufWriter::new(f);
+
+            w.write_all(MAGIC)?;
+            w.write_u32::<LittleEndian>(VERSION)?;
+            w.write_u32::<LittleEndian>(self.dim() as u32)?;
+            w.write_u32::<LittleEndian>(self.m as u32)?;
+            for &c in &self.codebooks {
+                w.write_f32::<LittleEndian>(c)?;
+            }
+            for (id, code) in self.ids.iter().zip(self.codes.chunks_exact(self.m)) {
+                w.write_u64::<LittleEndian>(*id)?;
+                w.write_all(code)?;
+            }
+
+            w.flush()?;
+            w.get_ref().sync_all()?;
+        }
+        fs::rename(&tmp_path, &self.pq_path)
+    }
+
+    fn append_codes(&self, ids: &[u64], codes: &[u8]) -> io::Result<()> {
+        let f = OpenOptions::new().append(true).open(&self.pq_path)?;
+        let mut w = BufWriter::new(f);
+        for (id, code) in ids.iter().zip(codes.chunks_exact(self.m)) {
+            w.write_u64::<LittleEndian>(*id)?;
+            w.write_all(code)?;
+        }
+        w.flush()
+    }
+}
+
+impl VectorIndex for PqIndex {
+    fn dim(&self) -> usize {
+        PqIndex::dim(self)
+    }
+
+    fn metric(&self) -> Metric {
+        PqIndex::metric(self)
+    }
+
+    fn template(&self) -> u32 {
+        PqIndex::template(self)
+    }
+
+    fn set_template(&mut self, template: u32) -> io::Result<()> {
+        PqIndex::set_template(self, template)
+    }
+
+    fn append(&mut self, vec: &[f32]) -> io::Result<u64> {
+        PqIndex::append(self, vec)
+    }
+
+    fn delete(&mut self, id: u64) -> io::Result<bool> {
+        PqIndex::delete(self, id)
+    }
+
+    fn replace(&mut self, id: u64, vec: &[f32]) -> io::Result<bool> {
+        PqIndex::replace(self, id, vec)
+    }
+
+    fn compact(&mut self) -> io::Result<CompactReport> {
+        PqIndex::compact(self)
+    }
+
+    fn search(&self, query: &[f32], top_k: usize) -> io::Result<Vec<(u64, f32)>> {
+        PqIndex::search(self, query, top_k, &SearchParams::default())
+    }
+
+    fn search_with(
+        &self,
+        query: &[f32],
+        top_k: usize,
+        params: &SearchParams,
+    ) -> io::Result<Vec<(u64, f32)>> {
+        PqIndex::search(self, query, top_k, params)
+    }
+
+    fn train(&mut self) -> io::Result<TrainReport> {
+        PqIndex::train(self)
+    }
+
+    fn search_range(
+        &self,
+        query: &[f32],
+        max_distance: f32,
+        cap: Option<usize>,
+        params: &SearchParams,
+    ) -> io::Result<Vec<(u64, f32)>> {
+        PqIndex::search_range(self, query, max_distance, cap, params)
+    }
+
+    fn ids(&self) -> Vec<u64> {
+        PqIndex::ids(self)
+    }
+
+    fn is_deleted(&self, id: u64) -> bool {
+        PqIndex::is_deleted(self, id)
+    }
+
+    fn vectors(&self, ids: &[u64]) -> io::Result<Vec<Option<Vec<f32>>>> {
+        PqIndex::vectors(self, ids)
+    }
+}
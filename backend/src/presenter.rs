@@ -6,6 +6,8 @@ use axum::{
 use serde::Serialize;
 use serde_json::json;
 
+use crate::schema::FieldError;
+
 #[derive(Serialize)]
 pub struct ErrorResponse {
     pub status: bool,
@@ -19,6 +21,42 @@ pub fn res_success<T: Serialize>(data: T) -> Response {
     (StatusCode::OK, Json(body)).into_response()
 }
 
+/// หนึ่งหน้าของผล search: `data` + จำนวนที่เข้าเกณฑ์ทั้งหมด + cursor ของหน้าถัดไป
+pub fn res_page<T: Serialize>(
+    data: T,
+    total_considered: usize,
+    next_cursor: Option<String>,
+) -> Response {
+    let body = json!({
+        "status": true,
+        "data": data,
+        "total_considered": total_considered,
+        "next_cursor": next_cursor,
+    });
+    (StatusCode::OK, Json(body)).into_response()
+}
+
+/// หนึ่งหน้าของ list document: `items` (ไม่มี distance/score) + cursor ของหน้าถัดไป
+pub fn res_list<T: Serialize>(items: T, total: usize, next_cursor: Option<String>) -> Response {
+    let body = json!({
+        "status": true,
+        "items": items,
+        "total": total,
+        "next_cursor": next_cursor,
+    });
+    (StatusCode::OK, Json(body)).into_response()
+}
+
+/// payload ไม่ผ่าน schema: error แยกราย field
+pub fn res_field_errors(errors: Vec<FieldError>) -> Response {
+    let body = json!({
+        "status": false,
+        "error": "payload does not match the schema",
+        "fields": errors,
+    });
+    (StatusCode::UNPROCESSABLE_ENTITY, Json(body)).into_response()
+}
+
 pub fn res_error<E: std::error::Error>(err: E) -> Response {
     let body = ErrorResponse {
         status: false,
@@ -33,4 +71,4 @@ pub fn res_error_msg<T: Serialize>(err: T) -> Response {
         "error": err
     });
     (StatusCode::UNPROCESSABLE_ENTITY, Json(body)).into_response()
-}
\ No newline at end of file
+}
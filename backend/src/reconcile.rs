@@ -0,0 +1,67 @@
+use serde::Serialize;
+use std::collections::HashSet;
+use std::io;
+
+use crate::doc_store::DocumentStore;
+use crate::vector_index::VectorIndex;
+
+/// What the startup reconciliation between `reviews.index` and the document
+/// store found and fixed.
+#[derive(Debug, Default, Serialize)]
+pub struct ReconcileReport {
+    /// vector ที่ไม่มี metadata (create ค้างหลัง append vector) -> tombstone ใน index
+    pub orphan_vectors: Vec<u64>,
+    /// metadata ที่ vector ถูก tombstone แล้ว (delete ค้างหลังลบ vector) -> ลบ metadata ให้จบ
+    pub deleted_documents: Vec<u64>,
+    /// metadata ที่ index ไม่มี vector เลย (index หาย/สร้างใหม่/ขาด record) -> เก็บไว้เหมือนเดิม
+    /// ค้นไม่เจอจนกว่าจะรัน `backend reindex --rebuild`
+    pub documents_without_vector: Vec<u64>,
+}
+
+impl ReconcileReport {
+    pub fn is_clean(&self) -> bool {
+        self.orphan_vectors.is_empty()
+            && self.deleted_documents.is_empty()
+            && self.documents_without_vector.is_empty()
+    }
+}
+
+/// ทำให้ index กับ document store มี id ชุดเดียวกันเท่าที่รู้ว่าเป็นงานค้าง
+///
+/// create เขียน vector ก่อน metadata, delete tombstone vector ก่อนลบ metadata:
+/// สองกรณีนี้ยังไม่ได้ตอบ client ว่าสำเร็จ จึงย้อน/ทำให้จบได้
+/// metadata ที่ไม่มี vector แบบอื่นไม่ลบทิ้ง (metadata สร้างคืนจาก index ไม่ได้)
+pub fn reconcile(
+    index: &mut dyn VectorIndex,
+    documents: &mut dyn DocumentStore,
+) -> io::Result<ReconcileReport> {
+    let vector_ids: HashSet<u64> = index.ids().into_iter().collect();
+    let mut document_ids: HashSet<u64> = HashSet::with_capacity(documents.len());
+    documents.scan(&mut |id, _| {
+        document_ids.insert(id);
+    })?;
+
+    let mut report = ReconcileReport {
+        orphan_vectors: vector_ids.difference(&document_ids).copied().collect(),
+        ..Default::default()
+    };
+    for &id in document_ids.difference(&vector_ids) {
+        if index.is_deleted(id) {
+            report.deleted_documents.push(id);
+        } else {
+            report.documents_without_vector.push(id);
+        }
+    }
+    report.orphan_vectors.sort_unstable();
+    report.deleted_documents.sort_unstable();
+    report.documents_without_vector.sort_unstable();
+
+    for &id in &report.orphan_vectors {
+        index.delete(id)?;
+    }
+    for &id in &report.deleted_documents {
+        documents.delete(id)?;
+    }
+
+    Ok(report)
+}
@@ -0,0 +1,269 @@
+use redb::{
+    Database, MultimapTable, MultimapTableDefinition, MultimapTableHandle, ReadableTable,
+    ReadableTableMetadata, Table, TableDefinition, WriteTransaction,
+};
+use serde_json::Value;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::doc_store::DocumentStore;
+use crate::vector_index::CompactReport;
+
+// id -> document (JSON text)
+const DOCUMENTS: TableDefinition<u64, &str> = TableDefinition::new("documents");
+
+// ค่าของ field ที่ index -> id ของ document (secondary index, table ชื่อเดียวกับ field)
+fn by_field(field: &str) -> MultimapTableDefinition<'_, &'static str, u64> {
+    MultimapTableDefinition::new(field)
+}
+
+/// Documents in an embedded redb database (`reviews.redb`): a table of
+/// `id -> JSON` plus a `value -> id` multimap of the schema's `indexed` field
+/// (`product_id` for reviews). Every put/delete is one transaction that
+/// touches only its own document, so updates and deletes don't rewrite the
+/// rest of the data like the JSONL store does.
+pub struct RedbStore {
+    db: Database,
+    path: String,
+    len: usize,
+    // None = ไม่มี secondary index (filter ทุกแบบ scan)
+    indexed: Option<String>,
+}
+
+fn db_error(e: impl Into<redb::Error>) -> io::Error {
+    io::Error::other(e.into())
+}
+
+impl RedbStore {
+    pub fn open_or_create(path: impl Into<String>, indexed: Option<&str>) -> io::Result<Self> {
+        let path = path.into();
+        if let Some(parent) = Path::new(&path).parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let db = Database::create(&path).map_err(db_error)?;
+
+        // สร้าง table ไว้ก่อน (read txn เปิด table ที่ยังไม่มีไม่ได้)
+        let txn = db.begin_write().map_err(db_error)?;
+        txn.open_table(DOCUMENTS).map_err(db_error)?;
+        // index ของ field อื่น (schema เปลี่ยน field ที่ index) -> ทิ้ง, ไม่งั้นเปลี่ยนกลับแล้วได้ index เก่า
+        let mut existing = false;
+        let tables: Vec<_> = txn.list_multimap_tables().map_err(db_error)?.collect();
+        for table in tables {
+            if Some(table.name()) == indexed {
+                existing = true;
+            } else {
+                txn.delete_multimap_table(table).map_err(db_error)?;
+            }
+        }
+        if let Some(field) = indexed.filter(|_| !existing) {
+            // index ใหม่ของ document ที่มีอยู่แล้ว
+            let table = txn.open_table(DOCUMENTS).map_err(db_error)?;
+            let mut index = txn.open_multimap_table(by_field(field)).map_err(db_error)?;
+            for entry in table.iter().map_err(db_error)? {
+                let (id, text) = entry.map_err(db_error)?;
+                let doc: Value = serde_json::from_str(text.value())?;
+                if let Some(key) = doc.get(field).and_then(Value::as_str) {
+                    index.insert(key, id.value()).map_err(db_error)?;
+                }
+            }
+        }
+        txn.commit().map_err(db_error)?;
+
+        let len = {
+            let txn = db.begin_read().map_err(db_error)?;
+            let table = txn.open_table(DOCUMENTS).map_err(db_error)?;
+            table.len().map_err(db_error)? as usize
+        };
+
+        Ok(Self {
+            db,
+            path,
+            len,
+            indexed: indexed.map(str::to_string),
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn get_many(&self, ids: &[u64]) -> io::Result<Vec<Option<Value>>> {
+        let txn = self.db.begin_read().map_err(db_error)?;
+        let table = txn.open_table(DOCUMENTS).map_err(db_error)?;
+        ids.iter()
+            .map(|&id| match table.get(id).map_err(db_error)? {
+                Some(text) => Ok(Some(serde_json::from_str(text.value())?)),
+                None => Ok(None),
+            })
+            .collect()
+    }
+
+    pub fn put(&mut self, id: u64, doc: &Value) -> io::Result<()> {
+        self.put_all(&[(id, doc.clone())])
+    }
+
+    /// put หลาย document ใน transaction เดียว
+    pub fn put_all(&mut self, docs: &[(u64, Value)]) -> io::Result<()> {
+        let txn = self.db.begin_write().map_err(db_error)?;
+        let mut added = 0;
+        {
+            let mut table = txn.open_table(DOCUMENTS).map_err(db_error)?;
+            let mut index = self.open_index(&txn)?;
+            for (id, doc) in docs {
+                if insert(&mut table, index.as_mut(), *id, doc)? {
+                    added += 1;
+                }
+            }
+        }
+        txn.commit().map_err(db_error)?;
+
+        self.len += added;
+        Ok(())
+    }
+
+    pub fn delete(&mut self, id: u64) -> io::Result<bool> {
+        let txn = self.db.begin_write().map_err(db_error)?;
+        let removed = {
+            let mut table = txn.open_table(DOCUMENTS).map_err(db_error)?;
+            let mut index = self.open_index(&txn)?;
+            let old = table
+                .remove(id)
+                .map_err(db_error)?
+                .map(|text| text.value().to_string());
+            match old {
+                Some(old) => {
+                    unindex(index.as_mut(), id, &serde_json::from_str(&old)?)?;
+                    true
+                }
+                None => false,
+            }
+        };
+        txn.commit().map_err(db_error)?;
+
+        if removed {
+            self.len -= 1;
+        }
+        Ok(removed)
+    }
+
+    /// อ่านทุก document เรียงตาม id
+    pub fn scan(&self, f: &mut dyn FnMut(u64, &Value)) -> io::Result<()> {
+        let txn = self.db.begin_read().map_err(db_error)?;
+        let table = txn.open_table(DOCUMENTS).map_err(db_error)?;
+        for entry in table.iter().map_err(db_error)? {
+            let (id, text) = entry.map_err(db_error)?;
+            let doc: Value = serde_json::from_str(text.value())?;
+            f(id.value(), &doc);
+        }
+        Ok(())
+    }
+
+    pub fn lookup(&self, field: &str, value: &Value) -> io::Result<Option<Vec<u64>>> {
+        if self.indexed.as_deref() != Some(field) {
+            return Ok(None);
+        }
+        let Some(key) = value.as_str() else {
+            return Ok(None);
+        };
+        let txn = self.db.begin_read().map_err(db_error)?;
+        let index = txn.open_multimap_table(by_field(field)).map_err(db_error)?;
+        let ids = index
+            .get(key)
+            .map_err(db_error)?
+            .map(|id| id.map(|id| id.value()).map_err(db_error))
+            .collect::<io::Result<Vec<u64>>>()?;
+        Ok(Some(ids))
+    }
+
+    // secondary index ของ write txn นี้ (None = schema ไม่ได้กำหนด field ที่ index)
+    fn open_index<'txn>(&self, txn: &'txn WriteTransaction) -> io::Result<Option<Index<'txn>>> {
+        let Some(field) = &self.indexed else {
+            return Ok(None);
+        };
+        let table = txn.open_multimap_table(by_field(field)).map_err(db_error)?;
+        Ok(Some(Index {
+            field: field.clone(),
+            table,
+        }))
+    }
+
+    /// คืนพื้นที่ page ที่ว่างจากการแก้/ลบกลับให้ไฟล์
+    pub fn compact(&mut self) -> io::Result<CompactReport> {
+        let before = fs::metadata(&self.path)?.len();
+        self.db.compact().map_err(db_error)?;
+        let after = fs::metadata(&self.path)?.len();
+
+        Ok(CompactReport {
+            records_removed: 0,
+            bytes_reclaimed: before.saturating_sub(after),
+        })
+    }
+}
+
+struct Index<'txn> {
+    field: String,
+    table: MultimapTable<'txn, &'static str, u64>,
+}
+
+// เขียน document + แก้ secondary index, คืน true ถ้าเป็น id ใหม่
+fn insert(
+    table: &mut Table<u64, &str>,
+    mut index: Option<&mut Index>,
+    id: u64,
+    doc: &Value,
+) -> io::Result<bool> {
+    let text = doc.to_string();
+    let old = table
+        .insert(id, text.as_str())
+        .map_err(db_error)?
+        .map(|old| old.value().to_string());
+    if let Some(old) = &old {
+        unindex(index.as_deref_mut(), id, &serde_json::from_str(old)?)?;
+    }
+    if let Some(index) = index {
+        if let Some(key) = doc.get(&index.field).and_then(Value::as_str) {
+            index.table.insert(key, id).map_err(db_error)?;
+        }
+    }
+    Ok(old.is_none())
+}
+
+fn unindex(index: Option<&mut Index>, id: u64, doc: &Value) -> io::Result<()> {
+    if let Some(index) = index {
+        if let Some(key) = doc.get(&index.field).and_then(Value::as_str) {
+            index.table.remove(key, id).map_err(db_error)?;
+        }
+    }
+    Ok(())
+}
+
+impl DocumentStore for RedbStore {
+    fn len(&self) -> usize {
+        RedbStore::len(self)
+    }
+
+    fn get_many(&self, ids: &[u64]) -> io::Result<Vec<Option<Value>>> {
+        RedbStore::get_many(self, ids)
+    }
+
+    fn put(&mut self, id: u64, doc: &Value) -> io::Result<()> {
+        RedbStore::put(self, id, doc)
+    }
+
+    fn delete(&mut self, id: u64) -> io::Result<bool> {
+        RedbStore::delete(self, id)
+    }
+
+    fn scan(&self, f: &mut dyn FnMut(u64, &Value)) -> io::Result<()> {
+        RedbStore::scan(self, f)
+    }
+
+    fn lookup(&self, field: &str, value: &Value) -> io::Result<Option<Vec<u64>>> {
+        RedbStore::lookup(self, field, value)
+    }
+
+    fn compact(&mut self) -> io::Result<CompactReport> {
+        RedbStore::compact(self)
+    }
+}
@@ -0,0 +1,104 @@
+use fastembed::TextEmbedding;
+use serde::Serialize;
+use std::io;
+
+use crate::doc_store::DocumentStore;
+use crate::flat_index::{RvixBuilder, VectorSpec};
+use crate::template::EmbeddingTemplate;
+use crate::vector_index::VectorIndex;
+
+// จำนวน document ที่ embed ต่อครั้ง
+const BATCH: usize = 256;
+// ไฟล์ที่ index แต่ละแบบสร้างจาก RVIX (hnsw graph, ivf lists, pq/binary codes)
+// -> ลบตอน rebuild ให้ build ใหม่จาก index ใหม่ตอน open
+const DERIVED: [&str; 4] = ["hnsw", "ivf", "pq", "bq"];
+
+/// Result of `backend reindex`.
+#[derive(Debug, Serialize)]
+pub struct ReindexReport {
+    pub documents: usize,
+    pub template: String,
+    pub template_version: String,
+}
+
+/// embed ทุก document ใหม่ด้วย `template` แล้วแทน vector เดิม (id ไม่เปลี่ยน)
+///
+/// บันทึก template version ลง index หลัง replace ครบทุกตัวเท่านั้น
+/// ถ้าพังกลางทาง version ยังเป็นของเดิม -> server ไม่ยอม start จนกว่าจะรันใหม่จนจบ
+pub fn reindex(
+    embedder: &mut TextEmbedding,
+    index: &mut dyn VectorIndex,
+    documents: &dyn DocumentStore,
+    template: &EmbeddingTemplate,
+) -> io::Result<ReindexReport> {
+    let mut texts: Vec<(u64, String)> = Vec::with_capacity(documents.len());
+    documents.scan(&mut |id, doc| texts.push((id, template.render(doc))))?;
+
+    for batch in texts.chunks(BATCH) {
+        let batch_texts: Vec<&str> = batch.iter().map(|(_, text)| text.as_str()).collect();
+        let embeddings = embedder
+            .embed(batch_texts, None)
+            .map_err(|e| io::Error::other(format!("embedding error: {}", e)))?;
+        for ((id, _), vec) in batch.iter().zip(&embeddings) {
+            if !index.replace(*id, vec)? {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!(
+                        "document {} has no vector in the index; \
+                         run `backend reindex <collection> --rebuild` to build a fresh index",
+                        id
+                    ),
+                ));
+            }
+        }
+    }
+    index.set_template(template.version())?;
+
+    Ok(ReindexReport {
+        documents: texts.len(),
+        template: template.text().to_string(),
+        template_version: format!("{:08x}", template.version()),
+    })
+}
+
+/// embed ทุก document ลง index ใหม่ที่ `index_path` (id เดิมของ document) แทนไฟล์เดิม
+///
+/// ไม่เปิด index เดิมเลย -> ใช้ได้ตอน model/dim/metric เปลี่ยนหรือ index หาย/ขาด vector
+/// ไฟล์เดิมถูกแทนตอนเขียนไฟล์ใหม่ครบแล้วเท่านั้น; ivf/pq ต้อง train ใหม่หลัง rebuild
+pub fn rebuild(
+    embedder: &mut TextEmbedding,
+    index_path: &str,
+    spec: &VectorSpec,
+    documents: &dyn DocumentStore,
+    template: &EmbeddingTemplate,
+) -> io::Result<ReindexReport> {
+    let mut texts: Vec<(u64, String)> = Vec::with_capacity(documents.len());
+    documents.scan(&mut |id, doc| texts.push((id, template.render(doc))))?;
+    // RVIX เก็บ id เรียงจากน้อยไปมาก
+    texts.sort_unstable_by_key(|(id, _)| *id);
+
+    let mut builder = RvixBuilder::create(index_path, spec)?;
+    for batch in texts.chunks(BATCH) {
+        let batch_texts: Vec<&str> = batch.iter().map(|(_, text)| text.as_str()).collect();
+        let embeddings = embedder
+            .embed(batch_texts, None)
+            .map_err(|e| io::Error::other(format!("embedding error: {}", e)))?;
+        for ((id, _), vec) in batch.iter().zip(&embeddings) {
+            builder.push(*id, vec)?;
+        }
+    }
+
+    for ext in DERIVED {
+        match std::fs::remove_file(format!("{}.{}", index_path, ext)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+    }
+    builder.finish()?;
+
+    Ok(ReindexReport {
+        documents: texts.len(),
+        template: template.text().to_string(),
+        template_version: format!("{:08x}", template.version()),
+    })
+}
@@ -0,0 +1,267 @@
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::fs;
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+
+// string ที่ยาวกว่านี้ถือเป็นข้อความ ไม่เก็บค่าไว้ใน memory ให้ filter
+const MAX_FILTER_LENGTH: usize = 256;
+
+/// Typed schema of the documents of a collection (`reviews.schema.json` for reviews).
+/// Field order is the order documents are stored and returned in.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Schema {
+    pub fields: Vec<FieldSpec>,
+    // field (string/enum) ที่ document store ทำ secondary index ไว้ให้ filter `==` (redb เท่านั้น)
+    #[serde(default)]
+    pub indexed: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FieldSpec {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub kind: FieldType,
+    #[serde(default)]
+    pub required: bool,
+    // ค่าที่ใส่ให้เมื่อ field optional ไม่ได้ส่งมา
+    #[serde(default)]
+    pub default: Option<Value>,
+    // int/float: ช่วงค่าที่รับ (รวมขอบ)
+    #[serde(default)]
+    pub min: Option<f64>,
+    #[serde(default)]
+    pub max: Option<f64>,
+    // string: จำนวนตัวอักษรสูงสุด และ regex ที่ต้อง match
+    #[serde(default)]
+    pub max_length: Option<usize>,
+    #[serde(default, with = "pattern")]
+    pub pattern: Option<Regex>,
+    // enum: ค่าที่อนุญาต
+    #[serde(default)]
+    pub values: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FieldType {
+    String,
+    Int,
+    Float,
+    Bool,
+    Enum,
+    /// RFC 3339 string เช่น `2024-05-01T12:00:00Z`
+    Timestamp,
+}
+
+/// One rejected field of a payload.
+#[derive(Debug, Serialize)]
+pub struct FieldError {
+    pub field: String,
+    pub error: String,
+}
+
+impl Schema {
+    /// โหลด schema จากไฟล์ (ดู `from_value`)
+    pub fn load(path: &str) -> Result<Self, String> {
+        let content = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        let value: Value =
+            serde_json::from_str(&content).map_err(|e| format!("{}: {}", path, e))?;
+        Self::from_value(value, path)
+    }
+
+    /// parse schema แล้วเช็คว่า constraint ของแต่ละ field ใช้ได้ (default ต้องผ่าน constraint ด้วย)
+    /// `source` = ที่มาของ schema ใช้นำหน้า error
+    pub fn from_value(value: Value, source: &str) -> Result<Self, String> {
+        let schema: Schema =
+            serde_json::from_value(value).map_err(|e| format!("{}: {}", source, e))?;
+        if schema.fields.is_empty() {
+            return Err(format!("{}: schema needs at least one field", source));
+        }
+
+        for (i, field) in schema.fields.iter().enumerate() {
+            let invalid = |msg: &str| Err(format!("{}: field {}: {}", source, field.name, msg));
+            if field.name == "id" || field.name == "embedding" {
+                return invalid("name is generated by the server");
+            }
+            if schema.fields[..i].iter().any(|f| f.name == field.name) {
+                return invalid("duplicate field");
+            }
+            if field.kind == FieldType::Enum && field.values.is_empty() {
+                return invalid("enum needs `values`");
+            }
+            if let (Some(min), Some(max)) = (field.min, field.max) {
+                if min > max {
+                    return invalid("min is greater than max");
+                }
+            }
+            if let Some(default) = &field.default {
+                if let Err(msg) = field.check(default) {
+                    return invalid(&format!("default {}", msg));
+                }
+            }
+        }
+
+        if let Some(name) = &schema.indexed {
+            match schema.field(name) {
+                Some(f) if matches!(f.kind, FieldType::String | FieldType::Enum) => {}
+                Some(_) => {
+                    return Err(format!(
+                        "{}: indexed field {} must be a string or enum",
+                        source, name
+                    ))
+                }
+                None => {
+                    return Err(format!(
+                        "{}: indexed field {} is not in fields",
+                        source, name
+                    ))
+                }
+            }
+        }
+
+        Ok(schema)
+    }
+
+    /// ชื่อ field ตามลำดับใน schema
+    pub fn names(&self) -> Vec<String> {
+        self.fields.iter().map(|f| f.name.clone()).collect()
+    }
+
+    /// field ที่ store เก็บค่าไว้ใน memory ให้ filter ไม่ต้องอ่าน document (JSONL)
+    /// ทุก field ยกเว้น string ที่เป็นข้อความยาว (ไม่มี max_length หรือยาวกว่า MAX_FILTER_LENGTH)
+    pub fn filter_fields(&self) -> Vec<String> {
+        self.fields
+            .iter()
+            .filter(|f| {
+                f.kind != FieldType::String
+                    || f.max_length.is_some_and(|len| len <= MAX_FILTER_LENGTH)
+            })
+            .map(|f| f.name.clone())
+            .collect()
+    }
+
+    pub fn field(&self, name: &str) -> Option<&FieldSpec> {
+        self.fields.iter().find(|f| f.name == name)
+    }
+
+    /// เช็คทุก field ของ document ตาม schema แล้วคืน (field, ค่า) ที่เติม default แล้ว
+    /// เรียงตาม schema หรือ error ของทุก field ที่ไม่ผ่าน
+    pub fn validate(
+        &self,
+        doc: &Map<String, Value>,
+    ) -> Result<Vec<(String, Value)>, Vec<FieldError>> {
+        let mut errors: Vec<FieldError> = doc
+            .keys()
+            .filter(|k| self.field(k).is_none())
+            .map(|k| FieldError {
+                field: k.clone(),
+                error: "unknown field".to_string(),
+            })
+            .collect();
+
+        let mut out = Vec::with_capacity(self.fields.len());
+        for field in &self.fields {
+            let value = match doc.get(&field.name).filter(|v| !v.is_null()) {
+                Some(v) => v.clone(),
+                None if field.required => {
+                    errors.push(field.error("is required".to_string()));
+                    continue;
+                }
+                None => field.default.clone().unwrap_or(Value::Null),
+            };
+            if !value.is_null() {
+                if let Err(msg) = field.check(&value) {
+                    errors.push(field.error(msg));
+                    continue;
+                }
+            }
+            out.push((field.name.clone(), value));
+        }
+
+        if errors.is_empty() {
+            Ok(out)
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+impl FieldSpec {
+    fn error(&self, error: String) -> FieldError {
+        FieldError {
+            field: self.name.clone(),
+            error,
+        }
+    }
+
+    /// ค่าที่ไม่ใช่ null ผ่าน type และ constraint ของ field นี้หรือไม่
+    fn check(&self, value: &Value) -> Result<(), String> {
+        match self.kind {
+            FieldType::String => {
+                let s = value.as_str().ok_or("must be a string")?;
+                if let Some(max_length) = self.max_length {
+                    if s.chars().count() > max_length {
+                        return Err(format!("must be at most {} characters", max_length));
+                    }
+                }
+                if let Some(pattern) = &self.pattern {
+                    if !pattern.is_match(s) {
+                        return Err(format!("must match {}", pattern.as_str()));
+                    }
+                }
+                Ok(())
+            }
+            FieldType::Int => {
+                let n = match value {
+                    Value::Number(n) if n.is_i64() || n.is_u64() => n.as_f64().unwrap_or_default(),
+                    _ => return Err("must be an integer".to_string()),
+                };
+                self.check_range(n)
+            }
+            FieldType::Float => {
+                let n = value.as_f64().ok_or("must be a number")?;
+                self.check_range(n)
+            }
+            FieldType::Bool => value
+                .as_bool()
+                .map(|_| ())
+                .ok_or("must be true or false".into()),
+            FieldType::Enum => match value.as_str() {
+                Some(s) if self.values.iter().any(|v| v == s) => Ok(()),
+                _ => Err(format!("must be one of {}", self.values.join(", "))),
+            },
+            FieldType::Timestamp => value
+                .as_str()
+                .and_then(|s| OffsetDateTime::parse(s, &Rfc3339).ok())
+                .map(|_| ())
+                .ok_or("must be an RFC 3339 timestamp".into()),
+        }
+    }
+
+    fn check_range(&self, n: f64) -> Result<(), String> {
+        match (self.min, self.max) {
+            (Some(min), Some(max)) if n < min || n > max => {
+                Err(format!("must be between {} and {}", min, max))
+            }
+            (Some(min), None) if n < min => Err(format!("must be at least {}", min)),
+            (None, Some(max)) if n > max => Err(format!("must be at most {}", max)),
+            _ => Ok(()),
+        }
+    }
+}
+
+// regex ใน schema เก็บเป็น string, compile ตอนโหลด (regex ผิด = โหลด schema ไม่ผ่าน)
+mod pattern {
+    use regex::Regex;
+    use serde::{Deserialize, Deserializer};
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Option<Regex>, D::Error> {
+        Option::<String>::deserialize(d)?
+            .map(|p| Regex::new(&p).map_err(serde::de::Error::custom))
+            .transpose()
+    }
+}
@@ -0,0 +1,108 @@
+use serde_json::Value;
+
+use crate::schema::Schema;
+
+/// Template ที่ index ก่อนมี template (RVIX ก่อน v5) ใช้ embed
+pub const LEGACY_TEMPLATE: &str = "{review_body}";
+
+/// Text that gets embedded for a document, e.g. `{review_title}\n{review_body}`.
+///
+/// `{field}` is replaced by the value of a schema field (`{{` / `}}` for literal
+/// braces). Its version (CRC32 of the template text) is written to the index
+/// header, so vectors built with another template are detected on startup.
+#[derive(Debug, Clone)]
+pub struct EmbeddingTemplate {
+    text: String,
+    parts: Vec<Part>,
+}
+
+#[derive(Debug, Clone)]
+enum Part {
+    Literal(String),
+    Field(String),
+}
+
+impl EmbeddingTemplate {
+    /// parse template แล้วเช็คว่าทุก `{field}` มีใน schema
+    pub fn parse(text: &str, schema: &Schema) -> Result<Self, String> {
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut chars = text.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => name.push(c),
+                            None => return Err("unclosed `{` in embedding template".to_string()),
+                        }
+                    }
+                    let name = name.trim().to_string();
+                    if schema.field(&name).is_none() {
+                        return Err(format!(
+                            "embedding template uses `{{{}}}` which is not a schema field",
+                            name
+                        ));
+                    }
+                    if !literal.is_empty() {
+                        parts.push(Part::Literal(std::mem::take(&mut literal)));
+                    }
+                    parts.push(Part::Field(name));
+                }
+                '}' => return Err("unmatched `}` in embedding template (use `}}`)".to_string()),
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            parts.push(Part::Literal(literal));
+        }
+        if !parts.iter().any(|p| matches!(p, Part::Field(_))) {
+            return Err("embedding template must use at least one field".to_string());
+        }
+
+        Ok(Self {
+            text: text.to_string(),
+            parts,
+        })
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// version ที่บันทึกใน header ของ index (template เดียวกัน = version เดียวกัน)
+    pub fn version(&self) -> u32 {
+        version_of(&self.text)
+    }
+
+    /// ข้อความที่จะ embed ของ document (field ที่ไม่มี/null = ว่าง)
+    pub fn render(&self, doc: &Value) -> String {
+        let mut out = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Literal(s) => out.push_str(s),
+                Part::Field(name) => match doc.get(name) {
+                    Some(Value::String(s)) => out.push_str(s),
+                    Some(Value::Null) | None => {}
+                    Some(v) => out.push_str(&v.to_string()),
+                },
+            }
+        }
+        out
+    }
+}
+
+pub fn version_of(text: &str) -> u32 {
+    crc32fast::hash(text.as_bytes())
+}
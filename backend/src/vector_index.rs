@@ -0,0 +1,237 @@
+use serde::Serialize;
+use std::collections::HashSet;
+use std::io;
+use std::sync::Arc;
+
+use crate::distance::Metric;
+use crate::flat_index::{FlatIndex, VectorSpec};
+use crate::hnsw_index::HnswIndex;
+use crate::ivf_index::IvfIndex;
+use crate::model::{IndexKind, IndexSettings};
+use crate::pq_index::PqIndex;
+
+/// Per-query knobs; each index reads the ones it understands.
+#[derive(Debug, Default, Clone)]
+pub struct SearchParams {
+    /// IVF: จำนวน list ที่จะ scan (None = ค่าจาก config)
+    pub nprobe: Option<usize>,
+    /// PQ / binary prefilter: จำนวน candidate ที่ re-rank ด้วย vector เต็ม
+    pub rerank: Option<usize>,
+    /// metadata filter: คืนเฉพาะ id ในชุดนี้ (None = ทุก id); เช็คระหว่าง scan
+    pub allow: Option<Arc<HashSet<u64>>>,
+}
+
+impl SearchParams {
+    pub fn allows(&self, id: u64) -> bool {
+        self.allow.as_ref().is_none_or(|ids| ids.contains(&id))
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct TrainReport {
+    pub records: usize,
+    pub centroids: usize,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct CompactReport {
+    pub records_removed: usize,
+    pub bytes_reclaimed: u64,
+}
+
+/// Common interface of the vector indexes, so handlers don't care which one
+/// is configured in config.yml.
+pub trait VectorIndex: Send + Sync {
+    fn dim(&self) -> usize;
+
+    /// metric ที่ index ถูกสร้างมา (distance ที่ search คืนเป็นของ metric นี้)
+    fn metric(&self) -> Metric;
+
+    /// version ของ embedding template ที่ vector ใน index ถูกสร้างมา
+    fn template(&self) -> u32;
+
+    /// บันทึก template version หลัง reindex vector ทุกตัวด้วย template ใหม่แล้ว
+    fn set_template(&mut self, template: u32) -> io::Result<()>;
+
+    /// append vector แล้วคืน id ที่ index ออกให้
+    fn append(&mut self, vec: &[f32]) -> io::Result<u64>;
+
+    /// ลบ record ด้วย tombstone (คืน false ถ้าไม่มี id นี้หรือถูกลบไปแล้ว)
+    fn delete(&mut self, id: u64) -> io::Result<bool>;
+
+    /// แทน vector ของ record เดิม (id ไม่เปลี่ยน) คืน false ถ้าไม่มี id นี้หรือถูกลบไปแล้ว
+    fn replace(&mut self, id: u64, vec: &[f32]) -> io::Result<bool>;
+
+    /// เขียนไฟล์ใหม่โดยตัด record ที่ถูกลบออก
+    fn compact(&mut self) -> io::Result<CompactReport>;
+
+    /// คืน (id, distance) เรียงจากใกล้ไปไกล
+    fn search(&self, query: &[f32], top_k: usize) -> io::Result<Vec<(u64, f32)>>;
+
+    fn search_with(
+        &self,
+        query: &[f32],
+        top_k: usize,
+        _params: &SearchParams,
+    ) -> io::Result<Vec<(u64, f32)>> {
+        self.search(query, top_k)
+    }
+
+    /// ค้นหลาย query พร้อมกัน คืนผลต่อ query ตามลำดับ
+    fn search_batch(
+        &self,
+        queries: &[Vec<f32>],
+        top_k: usize,
+        params: &SearchParams,
+    ) -> io::Result<Vec<Vec<(u64, f32)>>> {
+        queries
+            .iter()
+            .map(|q| self.search_with(q, top_k, params))
+            .collect()
+    }
+
+    /// radius search: ทุก record ที่ distance <= `max_distance` (ผ่าน filter ใน `params`)
+    /// เรียงจากใกล้ไปไกล ตัดเหลือ `cap` ตัวแรกถ้ากำหนด; scan แบบ exact เสมอ
+    fn search_range(
+        &self,
+        query: &[f32],
+        max_distance: f32,
+        cap: Option<usize>,
+        params: &SearchParams,
+    ) -> io::Result<Vec<(u64, f32)>>;
+
+    /// id ของทุก record ที่ยังไม่ถูกลบ เรียงจากน้อยไปมาก
+    fn ids(&self) -> Vec<u64>;
+
+    /// record ของ `id` ถูกลบด้วย tombstone แล้ว (ยังไม่ compact)
+    fn is_deleted(&self, id: u64) -> bool;
+
+    /// vector ที่เก็บใน reviews.index ของแต่ละ id ตามลำดับที่ขอ (None = ไม่มี/ถูกลบแล้ว)
+    fn vectors(&self, ids: &[u64]) -> io::Result<Vec<Option<Vec<f32>>>>;
+
+    /// train/rebuild โครงสร้างของ index จาก vector ที่มีอยู่
+    fn train(&mut self) -> io::Result<TrainReport> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "this index kind does not need training",
+        ))
+    }
+}
+
+impl VectorIndex for FlatIndex {
+    fn dim(&self) -> usize {
+        FlatIndex::dim(self)
+    }
+
+    fn metric(&self) -> Metric {
+        FlatIndex::metric(self)
+    }
+
+    fn template(&self) -> u32 {
+        FlatIndex::template(self)
+    }
+
+    fn set_template(&mut self, template: u32) -> io::Result<()> {
+        FlatIndex::set_template(self, template)
+    }
+
+    fn append(&mut self, vec: &[f32]) -> io::Result<u64> {
+        FlatIndex::append(self, vec)
+    }
+
+    fn delete(&mut self, id: u64) -> io::Result<bool> {
+        FlatIndex::delete(self, id)
+    }
+
+    fn replace(&mut self, id: u64, vec: &[f32]) -> io::Result<bool> {
+        FlatIndex::replace(self, id, vec)
+    }
+
+    fn compact(&mut self) -> io::Result<CompactReport> {
+        FlatIndex::compact(self)
+    }
+
+    fn search(&self, query: &[f32], top_k: usize) -> io::Result<Vec<(u64, f32)>> {
+        FlatIndex::search(self, query, top_k)
+    }
+
+    fn search_with(
+        &self,
+        query: &[f32],
+        top_k: usize,
+        params: &SearchParams,
+    ) -> io::Result<Vec<(u64, f32)>> {
+        FlatIndex::search_with(self, query, top_k, params)
+    }
+
+    fn search_batch(
+        &self,
+        queries: &[Vec<f32>],
+        top_k: usize,
+        params: &SearchParams,
+    ) -> io::Result<Vec<Vec<(u64, f32)>>> {
+        FlatIndex::search_batch(self, queries, top_k, params)
+    }
+
+    fn search_range(
+        &self,
+        query: &[f32],
+        max_distance: f32,
+        cap: Option<usize>,
+        params: &SearchParams,
+    ) -> io::Result<Vec<(u64, f32)>> {
+        FlatIndex::search_range(self, query, max_distance, cap, params)
+    }
+
+    fn ids(&self) -> Vec<u64> {
+        FlatIndex::ids(self)
+    }
+
+    fn is_deleted(&self, id: u64) -> bool {
+        FlatIndex::is_deleted(self, id)
+    }
+
+    fn vectors(&self, ids: &[u64]) -> io::Result<Vec<Option<Vec<f32>>>> {
+        FlatIndex::vectors(self, ids)
+    }
+}
+
+pub fn open_index(
+    index_path: &str,
+    dim: usize,
+    model: &str,
+    template: u32,
+    settings: &IndexSettings,
+) -> io::Result<Box<dyn VectorIndex>> {
+    let spec = VectorSpec {
+        dim,
+        precision: settings.precision,
+        metric: settings.metric,
+        model: model.to_string(),
+        template,
+    };
+    match settings.kind {
+        IndexKind::Flat => {
+            let mut index = FlatIndex::open_or_create(index_path, &spec)?;
+            if settings.binary.enabled {
+                index = index.with_binary_prefilter(settings.binary.rescore)?;
+            }
+            Ok(Box::new(index))
+        }
+        IndexKind::Hnsw => Ok(Box::new(HnswIndex::open_or_create(
+            index_path,
+            &spec,
+            &settings.hnsw,
+        )?)),
+        IndexKind::Ivf => Ok(Box::new(IvfIndex::open_or_create(
+            index_path,
+            &spec,
+            &settings.ivf,
+        )?)),
+        IndexKind::Pq => Ok(Box::new(PqIndex::open_or_create(
+            index_path,
+            &spec,
+            &settings.pq,
+        )?)),
+    }
+}